@@ -1,5 +1,5 @@
 use anyhow::Result;
-use rag::{Rag, RagProcessableFile, RagProcessableFileType};
+use rag::{Rag, RagProcessableFile, RagProcessableFileType, SearchEvent, SearchOptions};
 use std::fs;
 use std::io::Write;
 use std::time::Instant;
@@ -15,17 +15,18 @@ async fn main() -> Result<()> {
         return Err(e.into());
     }
 
+    Rag::default().ensure_collection().await?;
+
     server::start_server().await;
     Ok(())
 }
 
 async fn prompt(rag: &Rag, question: &str) -> Result<()> {
-    let mut result = rag.search(question.into()).await?;
+    let mut result = rag.search(question.into(), SearchOptions::default()).await?;
     let mut stdout = io::stdout();
-    while let Some(res) = result.stream.next().await {
-        let responses = res.unwrap();
-        for resp in responses {
-            stdout.write_all(resp.response.as_bytes()).await.unwrap();
+    while let Some(event) = result.stream.next().await {
+        if let SearchEvent::Delta { text } = event {
+            stdout.write_all(text.as_bytes()).await.unwrap();
             stdout.flush().await.unwrap();
         }
     }
@@ -74,7 +75,7 @@ async fn embed_all(rag: &Rag) -> Result<()> {
             file_description: None,
         };
         let start_time = Instant::now();
-        match rag.insert(woodstock_data).await {
+        match rag.insert_with_progress(woodstock_data, |_| {}).await {
             Ok(_) => {
                 // Successfully inserted
                 let duration = start_time.elapsed();
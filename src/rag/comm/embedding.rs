@@ -1,30 +1,163 @@
-use std::env;
+use std::{collections::BTreeMap, env};
 
 use ollama_rs::generation::embeddings::request::GenerateEmbeddingsRequest;
 use anyhow::Result;
-use qdrant_client::qdrant::SearchPoints;
+use qdrant_client::qdrant::{Condition, Filter, SearchPoints, SparseIndices};
 
-use crate::rag::models::chunks::EmbeddedChunk;
+use crate::rag::models::{chunks::{DocumentMetadata, EmbeddedChunk}, SearchOptions};
 
 pub trait Embeddable {
     fn try_into_embed(&self) -> GenerateEmbeddingsRequest;
     fn set_embedding_vectors(&mut self, embedding_vector: Vec<EmbeddingVector>);
-    fn prepare_for_upload(self, parent_doc_id: String, doc_summary: Option<String>, tags: Vec<String>) -> Result<Vec<EmbeddedChunk>>;
+    fn prepare_for_upload(self, document: &DocumentMetadata) -> Result<Vec<EmbeddedChunk>>;
 }
 
 #[derive(Debug, Clone)]
 pub struct EmbeddingVector(pub Vec<f32>);
 
-impl Into<SearchPoints> for EmbeddingVector {
-    fn into(self) -> SearchPoints {
+impl EmbeddingVector {
+    /// Element-wise mean of the two vectors.
+    pub fn average(&self, other: &EmbeddingVector) -> EmbeddingVector {
+        EmbeddingVector(self.0.iter().zip(&other.0).map(|(a, b)| (a + b) / 2.0).collect())
+    }
+
+    pub fn into_search_points(self, options: &SearchOptions) -> SearchPoints {
         let qdrant_collection = env::var("QDRANT_COLLECTION").expect("QDRANT_COLLECTION not defined");
         SearchPoints { 
             collection_name: qdrant_collection, 
             vector: self.0, 
-            limit: 10,
+            limit: options.candidate_limit(),
+            score_threshold: options.score_threshold,
+            filter: search_filter(options),
             with_payload: Some(true.into()),
-            with_vectors: Some(false.into()),
+            with_vectors: Some(options.mmr_lambda.is_some().into()),
             ..Default::default()
         }
     }
 }
+
+/// Name of the sparse vector holding the keyword (BM25) representation of a chunk.
+pub const KEYWORD_VECTOR_NAME: &str = "bm25";
+
+/// BM25 term frequency saturation parameter.
+const BM25_K1: f32 = 1.2;
+
+/// BM25 document length normalization parameter.
+const BM25_B: f32 = 0.75;
+
+/// Average chunk length in terms used for the length normalization.
+///
+/// Qdrant keeps no length statistics for sparse vectors, so this is the word count the
+/// hierarchical chunking aims for rather than a collection average.
+const BM25_AVGDL: f32 = 250.0;
+
+/// Sparse bag-of-words vector used for keyword retrieval.
+///
+/// Terms are hashed into the index space and document values carry the BM25 term frequency
+/// component, saturated and normalized by the chunk length. The IDF part of BM25 is applied
+/// by Qdrant through the `Idf` modifier of the sparse vector, so query values are plain ones.
+#[derive(Debug, Clone)]
+pub struct KeywordVector {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+impl KeywordVector {
+    pub fn from_document(text: &str) -> Self {
+        Self::from_term_counts(text, |tf, length| {
+            let norm = 1.0 - BM25_B + BM25_B * length / BM25_AVGDL;
+            tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm)
+        })
+    }
+
+    pub fn from_query(text: &str) -> Self {
+        Self::from_term_counts(text, |_, _| 1.0)
+    }
+
+    /// Builds the vector from the term counts of `text`, `weight` gets the count and the number of terms.
+    fn from_term_counts<F>(text: &str, weight: F) -> Self where F: Fn(f32, f32) -> f32 {
+        let terms = tokenize(text);
+        let length = terms.len() as f32;
+        let mut counts: BTreeMap<u32, f32> = BTreeMap::new();
+        for term in terms {
+            *counts.entry(term_index(&term)).or_insert(0.0) += 1.0;
+        }
+        Self {
+            indices: counts.keys().cloned().collect(),
+            values: counts.values().map(|tf| weight(*tf, length)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn into_search_points(self, options: &SearchOptions) -> SearchPoints {
+        let qdrant_collection = env::var("QDRANT_COLLECTION").expect("QDRANT_COLLECTION not defined");
+        SearchPoints {
+            collection_name: qdrant_collection,
+            vector: self.values,
+            sparse_indices: Some(SparseIndices { data: self.indices }),
+            vector_name: Some(KEYWORD_VECTOR_NAME.to_string()),
+            limit: options.candidate_limit(),
+            filter: search_filter(options),
+            with_payload: Some(true.into()),
+            with_vectors: Some(options.mmr_lambda.is_some().into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Lowercased alphanumeric terms, so course codes like `MAT-101` become `mat` and `101`.
+fn tokenize(text: &str) -> Vec<String> {
+    text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+/// FNV-1a hash of the term, stable across runs and platforms.
+fn term_index(term: &str) -> u32 {
+    term.bytes().fold(0x811c9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193))
+}
+
+/// Payload filter built from the tag list and filter expression of the search.
+fn search_filter(options: &SearchOptions) -> Option<Filter> {
+    let mut conditions: Vec<Condition> = vec![];
+    if !options.tags.is_empty() {
+        conditions.push(Condition::matches("tags", options.tags.clone()));
+    }
+    if let Some(filter) = &options.filter {
+        conditions.push(Filter::from(filter).into());
+    }
+    if conditions.is_empty() {
+        None
+    } else {
+        Some(Filter::must(conditions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weight_of(vector: &KeywordVector, term: &str) -> f32 {
+        let index = vector.indices.iter().position(|i| *i == term_index(term)).unwrap();
+        vector.values[index]
+    }
+
+    #[test]
+    fn longer_chunks_weigh_a_term_less() {
+        let short = KeywordVector::from_document("erasmus exchange");
+        let long = KeywordVector::from_document(&format!("erasmus {}", "filler ".repeat(500)));
+        assert!(weight_of(&short, "erasmus") > weight_of(&long, "erasmus"));
+    }
+
+    #[test]
+    fn average_length_chunks_get_plain_saturation() {
+        let text = format!("erasmus {}", "filler ".repeat(BM25_AVGDL as usize - 1));
+        let expected = (BM25_K1 + 1.0) / (1.0 + BM25_K1);
+        assert!((weight_of(&KeywordVector::from_document(&text), "erasmus") - expected).abs() < 1e-6);
+    }
+}
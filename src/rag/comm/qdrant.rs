@@ -1,31 +1,153 @@
-use std::env;
+use std::{env, sync::atomic::{AtomicBool, Ordering}};
 
 use once_cell::sync::Lazy;
-use qdrant_client::{qdrant::{PointStruct, SearchResponse, UpsertPointsBuilder}, Qdrant};
-use tokio::sync::Mutex;
-use anyhow::Result;
+use qdrant_client::{qdrant::{vectors_config::Config, Condition, CountPointsBuilder, CreateCollectionBuilder, CreateFieldIndexCollectionBuilder, DeletePointsBuilder, Distance, FieldType, Filter, Modifier, PointStruct, Range, RetrievedPoint, ScrollPointsBuilder, SearchResponse, SparseVectorParamsBuilder, SparseVectorsConfigBuilder, UpsertPointsBuilder, VectorParamsBuilder}, Qdrant};
+use anyhow::{anyhow, Result};
 
-use crate::rag::models::chunks::EmbeddedChunk;
+use crate::rag::models::{chunks::EmbeddedChunk, SearchOptions};
 
-use super::embedding::EmbeddingVector;
+use super::embedding::{EmbeddingVector, KeywordVector, KEYWORD_VECTOR_NAME};
 
 
 /// Static global client for accessing the Qdrant database.
 ///
 /// This variable initializes a Qdrant client connection that is used to interact with the Qdrant vector database.
-/// It is lazily instantiated and locked via a mutex to ensure thread-safe access across asynchronous tasks.
+/// It is lazily instantiated and shared without a lock, the client multiplexes concurrent requests over its channel.
 ///
 /// # Panics
 /// - Panics if the connection to the Qdrant database cannot be established, indicating a configuration or network issue.
-static QDRANT_CLIENT: Lazy<Mutex<Qdrant>> = Lazy::new(|| {
+static QDRANT_CLIENT: Lazy<Qdrant> = Lazy::new(|| {
     let qdrant_server = env::var("QDRANT_SERVER").expect("QDRANT_SERVER not defined");
-    let client = match Qdrant::from_url(&qdrant_server).build() {
+    match Qdrant::from_url(&qdrant_server).build() {
         Ok(c) => c,
         Err(e) => panic!("Can't establish Qdrant DB connection: {:#?}", e),
-    };
-    Mutex::new(client)
+    }
 });
 
+/// Whether the collection has the sparse keyword vector, set by `ensure_collection`.
+///
+/// Collections created before hybrid search only store the dense vector. Those keep working,
+/// keyword retrieval is just skipped until the collection is recreated.
+static KEYWORD_SEARCH: AtomicBool = AtomicBool::new(false);
+
+/// Payload fields that are filtered on, indexed when the collection is prepared.
+const PAYLOAD_INDEXES: [(&str, FieldType); 7] = [
+    ("doc_id", FieldType::Keyword),
+    ("doc_seq_num", FieldType::Integer),
+    ("content_hash", FieldType::Keyword),
+    ("tags", FieldType::Keyword),
+    ("file_type", FieldType::Keyword),
+    ("original_name", FieldType::Keyword),
+    ("ingested_at", FieldType::Datetime),
+];
+
+/// Makes sure `QDRANT_COLLECTION` exists and can store vectors of the given dimension.
+///
+/// A missing collection is created with the distance metric from `QDRANT_DISTANCE`
+/// (`Cosine` when unset). An existing collection is validated. In both cases any
+/// missing payload indexes are created.
+///
+/// New collections also get the sparse `bm25` vector used for keyword retrieval.
+///
+/// # Errors
+/// - Returns an error if the collection's vector size doesn't match `dimension`, if it uses named
+///   vectors, or if any of the Qdrant requests fail.
+pub async fn ensure_collection(dimension: u64) -> Result<()> {
+    let client = &*QDRANT_CLIENT;
+    let qdrant_collection = env::var("QDRANT_COLLECTION").expect("QDRANT_COLLECTION not defined");
+    let distance = configured_distance()?;
+
+    if !client.collection_exists(&qdrant_collection).await? {
+        let mut sparse_vectors = SparseVectorsConfigBuilder::default();
+        sparse_vectors.add_named_vector_params(
+            KEYWORD_VECTOR_NAME,
+            SparseVectorParamsBuilder::default().modifier(Modifier::Idf),
+        );
+        println!("Creating qdrant collection '{}' ({} dimensions, {:?})...", qdrant_collection, dimension, distance);
+        client
+            .create_collection(
+                CreateCollectionBuilder::new(qdrant_collection.clone())
+                    .vectors_config(VectorParamsBuilder::new(dimension, distance))
+                    .sparse_vectors_config(sparse_vectors),
+            )
+            .await?;
+    }
+
+    let info = client
+        .collection_info(&qdrant_collection)
+        .await?
+        .result
+        .ok_or(anyhow!("Can't read the info of qdrant collection '{}'", qdrant_collection))?;
+
+    let collection_params = info.config.and_then(|c| c.params);
+    let has_keyword_vector = collection_params
+        .as_ref()
+        .and_then(|p| p.sparse_vectors_config.as_ref())
+        .is_some_and(|s| s.map.contains_key(KEYWORD_VECTOR_NAME));
+    let vectors_config = collection_params
+        .and_then(|p| p.vectors_config)
+        .and_then(|v| v.config);
+
+    let params = match vectors_config {
+        Some(Config::Params(params)) => params,
+        Some(Config::ParamsMap(_)) => return Err(anyhow!(
+            "Qdrant collection '{}' uses named vectors, expected a single unnamed vector", qdrant_collection
+        )),
+        None => return Err(anyhow!("Can't read the vector config of qdrant collection '{}'", qdrant_collection)),
+    };
+
+    if params.size != dimension {
+        return Err(anyhow!(
+            "Qdrant collection '{}' stores vectors of size {}, but the embedding model produces {}. \
+            Recreate the collection or point QDRANT_COLLECTION to a different one.",
+            qdrant_collection, params.size, dimension
+        ));
+    }
+
+    if params.distance != distance as i32 {
+        eprintln!(
+            "Qdrant collection '{}' uses distance {:?}, but QDRANT_DISTANCE is {:?}",
+            qdrant_collection,
+            Distance::try_from(params.distance).unwrap_or(Distance::UnknownDistance),
+            distance
+        );
+    }
+
+    if !has_keyword_vector {
+        eprintln!(
+            "Qdrant collection '{}' has no '{}' sparse vector, keyword search is disabled until it is recreated",
+            qdrant_collection, KEYWORD_VECTOR_NAME
+        );
+    }
+    KEYWORD_SEARCH.store(has_keyword_vector, Ordering::Relaxed);
+
+    for (field, field_type) in PAYLOAD_INDEXES {
+        if info.payload_schema.contains_key(field) {
+            continue;
+        }
+        println!("Creating payload index on '{}'...", field);
+        client
+            .create_field_index(
+                CreateFieldIndexCollectionBuilder::new(qdrant_collection.clone(), field, field_type)
+                    .wait(true),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn configured_distance() -> Result<Distance> {
+    let distance = env::var("QDRANT_DISTANCE").unwrap_or("Cosine".to_string());
+    match distance.to_lowercase().as_str() {
+        "cosine" => Ok(Distance::Cosine),
+        "euclid" => Ok(Distance::Euclid),
+        "dot" => Ok(Distance::Dot),
+        "manhattan" => Ok(Distance::Manhattan),
+        _ => Err(anyhow!("Unknown QDRANT_DISTANCE '{}', expected Cosine, Euclid, Dot or Manhattan", distance)),
+    }
+}
+
 /// Performs a vector search in the Qdrant database using a given embedding tensor.
 ///
 /// This function converts the provided tensor into a vector of `f32` values and uses it to query the Qdrant database.
@@ -33,6 +155,7 @@ static QDRANT_CLIENT: Lazy<Mutex<Qdrant>> = Lazy::new(|| {
 ///
 /// # Parameters
 /// - `embedding`: The tensor representing an embedding that needs to be searched within the Qdrant vector space.
+/// - `options`: Limit, score threshold and tag filter applied to the search.
 ///
 /// # Returns
 /// Returns a `Result` containing the search response from Qdrant if successful. This response includes details of the
@@ -40,22 +163,42 @@ static QDRANT_CLIENT: Lazy<Mutex<Qdrant>> = Lazy::new(|| {
 ///
 /// # Errors
 /// - Returns an error if the tensor conversion fails or if the Qdrant search query encounters issues.
-pub async fn vector_search(embedding: EmbeddingVector) -> Result<SearchResponse> {
-    let client = QDRANT_CLIENT.lock().await;
+pub async fn vector_search(embedding: EmbeddingVector, options: &SearchOptions) -> Result<SearchResponse> {
+    let client = &*QDRANT_CLIENT;
     let search_result = client
-        .search_points(embedding)
+        .search_points(embedding.into_search_points(options))
         .await?;
     Ok(search_result.into())
 }
 
+/// Whether the collection supports keyword retrieval, see `ensure_collection`.
+pub fn keyword_search_enabled() -> bool {
+    KEYWORD_SEARCH.load(Ordering::Relaxed)
+}
+
+/// Performs a BM25 keyword search against the sparse `bm25` vector of the collection.
+///
+/// Takes the same limit and payload filters as `vector_search`. The score threshold isn't applied,
+/// BM25 scores aren't comparable to the dense similarity it is meant for.
+///
+/// # Errors
+/// - Returns an error if the Qdrant search query fails.
+pub async fn keyword_search(keywords: KeywordVector, options: &SearchOptions) -> Result<SearchResponse> {
+    let client = &*QDRANT_CLIENT;
+    let search_result = client
+        .search_points(keywords.into_search_points(options))
+        .await?;
+    Ok(search_result)
+}
+
 pub async fn insert_chunks_to_qdrant(embedded_chunks: Vec<EmbeddedChunk>) -> Result<()> {
     println!("Upserting to qdrant...");
-    let client = QDRANT_CLIENT.lock().await;
+    let client = &*QDRANT_CLIENT;
     let qdrant_collection = env::var("QDRANT_COLLECTION").expect("QDRANT_COLLECTION not defined");
 
     let points: Vec<PointStruct> = embedded_chunks
         .into_iter()
-        .map(|c| c.into())
+        .map(|c| if keyword_search_enabled() { c.into_hybrid_point() } else { c.into() })
         .collect();
 
     client
@@ -63,4 +206,113 @@ pub async fn insert_chunks_to_qdrant(embedded_chunks: Vec<EmbeddedChunk>) -> Res
         .await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Deletes every point belonging to the document with the given `doc_id`.
+///
+/// # Errors
+/// - Returns an error if the Qdrant delete request fails.
+pub async fn delete_document_points(doc_id: &str) -> Result<()> {
+    println!("Deleting points of '{}' from qdrant...", doc_id);
+    let client = &*QDRANT_CLIENT;
+    let qdrant_collection = env::var("QDRANT_COLLECTION").expect("QDRANT_COLLECTION not defined");
+
+    client
+        .delete_points(
+            DeletePointsBuilder::new(qdrant_collection)
+                .points(document_filter(doc_id))
+                .wait(true),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Counts the points stored for the document with the given `doc_id`.
+///
+/// # Errors
+/// - Returns an error if the Qdrant count request fails.
+pub async fn count_document_points(doc_id: &str) -> Result<u64> {
+    let client = &*QDRANT_CLIENT;
+    let qdrant_collection = env::var("QDRANT_COLLECTION").expect("QDRANT_COLLECTION not defined");
+
+    let response = client
+        .count(
+            CountPointsBuilder::new(qdrant_collection)
+                .filter(document_filter(doc_id))
+                .exact(true),
+        )
+        .await?;
+
+    Ok(response.result.map_or(0, |r| r.count))
+}
+
+/// Checks whether any point in the collection was ingested from a file with the given content hash.
+///
+/// # Errors
+/// - Returns an error if the Qdrant count request fails.
+pub async fn content_hash_exists(content_hash: &str) -> Result<bool> {
+    let client = &*QDRANT_CLIENT;
+    let qdrant_collection = env::var("QDRANT_COLLECTION").expect("QDRANT_COLLECTION not defined");
+
+    let response = client
+        .count(
+            CountPointsBuilder::new(qdrant_collection)
+                .filter(Filter::must([Condition::matches("content_hash", content_hash.to_string())]))
+                .exact(true),
+        )
+        .await?;
+
+    Ok(response.result.map_or(0, |r| r.count) > 0)
+}
+
+/// Scrolls through every point matching `filter` (or the whole collection when `None`), returning payloads only.
+///
+/// # Errors
+/// - Returns an error if any of the Qdrant scroll requests fail.
+pub async fn scroll_points(filter: Option<Filter>) -> Result<Vec<RetrievedPoint>> {
+    let client = &*QDRANT_CLIENT;
+    let qdrant_collection = env::var("QDRANT_COLLECTION").expect("QDRANT_COLLECTION not defined");
+
+    let mut points = vec![];
+    let mut offset = None;
+    loop {
+        let mut request = ScrollPointsBuilder::new(qdrant_collection.clone())
+            .limit(256)
+            .with_payload(true)
+            .with_vectors(false);
+        if let Some(f) = &filter {
+            request = request.filter(f.clone());
+        }
+        if let Some(o) = offset {
+            request = request.offset(o);
+        }
+
+        let response = client.scroll(request).await?;
+        points.extend(response.result);
+        offset = response.next_page_offset;
+        if offset.is_none() {
+            break;
+        }
+    }
+
+    Ok(points)
+}
+
+/// Filter matching the points of document `doc_id` whose `doc_seq_num` lies in `from..=to`.
+pub fn sequence_filter(doc_id: &str, from: i32, to: i32) -> Filter {
+    Filter::must([
+        Condition::matches("doc_id", doc_id.to_string()),
+        Condition::range("doc_seq_num", Range { gte: Some(from as f64), lte: Some(to as f64), ..Default::default() }),
+    ])
+}
+
+/// Filter matching all points of the document with the given `doc_id`.
+pub fn document_filter(doc_id: &str) -> Filter {
+    Filter::must([Condition::matches("doc_id", doc_id.to_string())])
+}
+
+/// Filter matching every point of documents ingested from a file named `original_name`.
+pub fn original_name_filter(original_name: &str) -> Filter {
+    Filter::must([Condition::matches("original_name", original_name.to_string())])
+}
@@ -7,5 +7,7 @@ pub struct LoadedFile {
     pub original_file_description: Option<String>,
     pub syntetic_file_description: Option<String>,
     pub internal_id: String,
+    pub original_name: String,
+    pub content_hash: String,
     pub tags: Option<Vec<String>>,
 }
\ No newline at end of file
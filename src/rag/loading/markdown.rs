@@ -2,7 +2,7 @@ use std::{fs::File, io::{BufReader, Read}};
 use anyhow::Result;
 use crate::rag::RagProcessableFile;
 
-use super::{loaded_data::LoadedFile, FileLoader};
+use super::{content_hash, loaded_data::LoadedFile, FileLoader};
 
 pub struct MarkdownFileLoader;
 
@@ -16,6 +16,8 @@ impl FileLoader for MarkdownFileLoader {
             file_type: file.file_type.clone(),
             content: buffer,
             internal_id: file.internal_id.clone(),
+            original_name: file.original_name.clone(),
+            content_hash: content_hash(&file.path)?,
             tags: file.tags.clone(),
             original_file_description: file.file_description.clone(),
             syntetic_file_description: None,
@@ -2,6 +2,8 @@ use loaded_data::LoadedFile;
 use anyhow::{Result, anyhow};
 use markdown::MarkdownFileLoader;
 use pdf::PdfFileLoader;
+use sha2::{Digest, Sha256};
+use std::{fs, path::Path};
 use text::TextFileLoader;
 
 use super::{models::RagProcessableFileType, RagProcessableFile};
@@ -18,6 +20,13 @@ trait FileLoader {
     fn load_file(file: &RagProcessableFile) -> Result<LoadedFile>;
 }
 
+/// Hex encoded SHA-256 of the raw file bytes, used to detect unchanged files.
+pub fn content_hash(path: &Path) -> Result<String> {
+    let bytes = fs::read(path)?;
+    let digest = Sha256::digest(&bytes);
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
 pub fn load_file(file: &RagProcessableFile) -> Result<LoadedFile> {
     match file.file_type {
         RagProcessableFileType::Text => TextFileLoader::load_file(file),
@@ -2,7 +2,7 @@ use crate::rag::RagProcessableFile;
 use anyhow::{Result, anyhow};
 use lopdf::Document;
 
-use super::{loaded_data::LoadedFile, FileLoader, RagProcessableFileType};
+use super::{content_hash, loaded_data::LoadedFile, FileLoader, RagProcessableFileType};
 
 pub struct PdfFileLoader;
 
@@ -30,6 +30,8 @@ impl FileLoader for PdfFileLoader {
             file_type: RagProcessableFileType::Pdf,
             content: extracted_text,
             internal_id: file.internal_id.clone(),
+            original_name: file.original_name.clone(),
+            content_hash: content_hash(&file.path)?,
             tags: file.tags.clone(),
             original_file_description: file.file_description.clone(),
             syntetic_file_description: None,
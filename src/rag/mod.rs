@@ -1,16 +1,16 @@
-use comm::{embedding::EmbeddingVector, qdrant::{insert_chunks_to_qdrant, vector_search}, OllamaClient};
+use comm::{embedding::{EmbeddingVector, KeywordVector}, qdrant::{content_hash_exists, count_document_points, delete_document_points, document_filter, ensure_collection, original_name_filter, insert_chunks_to_qdrant, keyword_search, keyword_search_enabled, scroll_points, vector_search}, OllamaClient};
 use anyhow::{Result, anyhow};
-use loading::load_file;
-use models::SearchResult;
+use loading::{content_hash, load_file};
+use models::{chunks::{EmbeddedChunk, ResultChunk}, DocumentChunk, DocumentOverview};
 use ollama_rs::generation::embeddings::request::{EmbeddingsInput, GenerateEmbeddingsRequest};
-use processing::{chunk, dedup, hype, prepare_for_upload, prompt, recursive_prompt};
+use processing::{best_score, chunk, condense_query, dedup, expand_neighbours, expand_query, fallback_events, grounded_events, is_confident, suggestions, group_chunks, group_documents, hype, hypothetical_answer, mmr, prepare_for_upload, prompt, reciprocal_rank_fusion, recursive_prompt, rerank};
 
 pub mod comm;
 mod loading;
 mod models;
 mod processing;
 
-pub use models::{RagProcessableFile, RagProcessableFileType};
+pub use models::{IngestStage, QueryMode, RagProcessableFile, RagProcessableFileType, Role, SearchEvent, SearchFilter, SearchOptions, SearchResult, Turn};
 
 #[derive(Debug, Default)]
 pub struct Rag {
@@ -19,30 +19,272 @@ pub struct Rag {
 
 
 impl Rag {
-    pub async fn insert(&self, file: RagProcessableFile) -> Result<()>{
-        let loaded_file = load_file(&file)?;
+    /// Runs the ingestion pipeline, calling `on_stage` before each stage starts.
+    pub async fn insert_with_progress<F>(&self, file: RagProcessableFile, on_stage: F) -> Result<()> where F: Fn(IngestStage) {
+        let embedded_chunks = self.prepare_chunks(&file, &on_stage).await?;
+        on_stage(IngestStage::Upserting);
+        insert_chunks_to_qdrant(embedded_chunks).await
+    }
+
+    /// Checks whether the exact contents of `file` were already ingested, so the LLM stages can be skipped.
+    pub async fn is_unchanged(&self, file: &RagProcessableFile) -> Result<bool> {
+        let hash = content_hash(&file.path)?;
+        content_hash_exists(&hash).await
+    }
+
+    /// Removes every chunk of the document with the given `doc_id` from the index.
+    ///
+    /// Returns `false` when the index holds no chunks for `doc_id`.
+    pub async fn delete(&self, doc_id: &str) -> Result<bool> {
+        if count_document_points(doc_id).await? == 0 {
+            return Ok(false);
+        }
+        delete_document_points(doc_id).await?;
+        Ok(true)
+    }
+
+    /// Replaces the document identified by `file.internal_id` with a fresh ingest of `file`.
+    ///
+    /// The old points are only deleted once the new chunks are embedded, so the document
+    /// stays searchable while the LLM stages run.
+    pub async fn reindex_with_progress<F>(&self, file: RagProcessableFile, on_stage: F) -> Result<()> where F: Fn(IngestStage) {
+        let embedded_chunks = self.prepare_chunks(&file, &on_stage).await?;
+        on_stage(IngestStage::Upserting);
+        delete_document_points(&file.internal_id).await?;
+        for legacy_id in self.legacy_doc_ids(&file).await? {
+            println!("Removing '{}', the copy of '{}' stored under its old id", legacy_id, file.internal_id);
+            delete_document_points(&legacy_id).await?;
+        }
+        insert_chunks_to_qdrant(embedded_chunks).await
+    }
+
+    /// Doc ids an earlier version of `file` may be indexed under, from before doc ids were
+    /// derived from the file name: `{n}_{name}` from `/build` and `{uuid}_{name}` from uploads.
+    async fn legacy_doc_ids(&self, file: &RagProcessableFile) -> Result<Vec<String>> {
+        let mut doc_ids: Vec<String> = scroll_points(Some(original_name_filter(&file.original_name)))
+            .await?
+            .into_iter()
+            .map(|p| ResultChunk::from(p).doc_id)
+            .filter(|doc_id| doc_id != &file.internal_id && is_legacy_doc_id(doc_id, &file.original_name))
+            .collect();
+        doc_ids.sort();
+        doc_ids.dedup();
+        Ok(doc_ids)
+    }
+
+    /// Lists every document in the collection.
+    pub async fn documents(&self) -> Result<Vec<DocumentOverview>> {
+        let points = scroll_points(None).await?;
+        Ok(group_documents(points.into_iter().map(|p| p.into()).collect()))
+    }
+
+    /// Returns the chunks of a document with the questions generated for each of them.
+    pub async fn document_chunks(&self, doc_id: &str) -> Result<Vec<DocumentChunk>> {
+        let points = scroll_points(Some(document_filter(doc_id))).await?;
+        Ok(group_chunks(points.into_iter().map(|p| p.into()).collect()))
+    }
+
+    async fn prepare_chunks<F>(&self, file: &RagProcessableFile, on_stage: &F) -> Result<Vec<EmbeddedChunk>> where F: Fn(IngestStage) {
+        on_stage(IngestStage::Loading);
+        let loaded_file = load_file(file)?;
+        on_stage(IngestStage::Chunking);
         let chunked_file = chunk(loaded_file, processing::ChunkingStrategy::Hierarchical(250, 30));
+        on_stage(IngestStage::Hype);
         let enriched_file = hype(chunked_file, &self.ollama).await;
-        let embedded_chunks = prepare_for_upload(enriched_file, &self.ollama).await?;
-        insert_chunks_to_qdrant(embedded_chunks).await
+        on_stage(IngestStage::Embedding);
+        prepare_for_upload(enriched_file, &self.ollama).await
     }
 
 
-    pub async fn search(&self, query: String) -> Result<SearchResult> {
-        let emb_query = GenerateEmbeddingsRequest::new(
-            "bge-m3".to_owned(), 
-            EmbeddingsInput::Single(query.clone())
+    /// Prepares the Qdrant collection for the embedding model, creating it on a fresh Qdrant.
+    ///
+    /// The vector dimension is probed by embedding a short text with the model.
+    pub async fn ensure_collection(&self) -> Result<()> {
+        let probe = GenerateEmbeddingsRequest::new(
+            "bge-m3".to_owned(),
+            EmbeddingsInput::Single("dimension probe".to_owned())
         );
-        let embedding = match self.ollama.embed(emb_query).await {
-            Ok(resp) => EmbeddingVector(resp.embeddings[0].clone()),
-            Err(e) => return Err(anyhow!(format!("Failed embedding the query: {}", e))),
+        let dimension = match self.ollama.embed(probe).await {
+            Ok(resp) => match resp.embeddings.first() {
+                Some(e) => e.len() as u64,
+                None => return Err(anyhow!("Embedding model returned no embeddings")),
+            },
+            Err(e) => return Err(anyhow!(format!("Failed probing the embedding model: {}", e))),
+        };
+        ensure_collection(dimension).await
+    }
+
+    pub async fn search(&self, query: String, options: SearchOptions) -> Result<SearchResult> {
+        self.chat(&[], query, options).await
+    }
+
+    /// Answers `message` as the next turn of a conversation.
+    ///
+    /// The history gives follow-up messages their subject for retrieval and is passed to the
+    /// model as the conversation so far.
+    pub async fn chat(&self, history: &[Turn], message: String, options: SearchOptions) -> Result<SearchResult> {
+        let query = condense_query(history, &message, &self.ollama).await;
+        let expanded_queries = match options.query_expansions {
+            0 => vec![],
+            count => expand_query(&query, count, &self.ollama).await,
+        };
+
+        let hypothetical = match options.query_mode {
+            QueryMode::Question => None,
+            QueryMode::Hyde | QueryMode::HydeAverage => hypothetical_answer(&query, &self.ollama).await,
+        };
+
+        let mut queries = vec![query.clone()];
+        queries.extend(expanded_queries.iter().cloned());
+        let mut texts = queries.clone();
+        texts.extend(hypothetical.iter().cloned());
+        let mut embeddings = self.embed_queries(texts).await?;
+        let query_embedding = embeddings[0].0.clone();
+        if hypothetical.is_some() {
+            if let Some(passage) = embeddings.pop() {
+                embeddings[0] = match options.query_mode {
+                    QueryMode::HydeAverage => embeddings[0].average(&passage),
+                    _ => passage,
+                };
+            }
+        }
+        let rankings = futures::future::join_all(queries
+            .iter()
+            .zip(embeddings)
+            .map(|(q, embedding)| self.retrieve(q, embedding, &options)))
+            .await
+            .into_iter()
+            .collect::<Result<Vec<Vec<ResultChunk>>>>()?;
+        let resp = if rankings.len() == 1 {
+            rankings.into_iter().flatten().collect()
+        } else {
+            let mut fused = reciprocal_rank_fusion(rankings.into_iter().map(|r| (r, 1.0)).collect());
+            fused.truncate(options.candidate_limit() as usize);
+            fused
         };
-        let resp = vector_search(embedding).await?;
         let resp = dedup(resp);
+        let best = best_score(&resp);
+        if let Some(min_relevance) = options.min_relevance {
+            if !is_confident(&resp, min_relevance) {
+                println!("Unanswered query (best score {:?} < {}): {}", best, min_relevance, query);
+                let suggestions = suggestions(&resp);
+                return Ok(SearchResult {
+                    stream: fallback_events(&message, &suggestions),
+                    query,
+                    expanded_queries,
+                    hypothetical_answer: hypothetical,
+                    fallback: true,
+                    suggestions,
+                    best_score: best,
+                    query_embedding,
+                    chunks: vec![],
+                    dropped: vec![],
+                });
+            }
+        }
+        let resp = match options.mmr_lambda {
+            Some(lambda) => mmr(resp, lambda, options.top_k as usize),
+            None => resp,
+        };
+        let resp = match options.rerank_top_n {
+            Some(top_n) => rerank(&query, resp, top_n, &self.ollama).await,
+            None => resp,
+        };
+        let resp = expand_neighbours(resp, options.neighbours).await?;
         println!("{:#?}", resp);
-        match recursive_prompt(query, resp, &self.ollama).await {
-            Ok(r) => Ok(r),
+        match recursive_prompt(message, history, resp, &self.ollama).await {
+            Ok(r) => {
+                let stream = if options.verify_grounding || options.grounding_threshold.is_some() {
+                    grounded_events(r.stream, &r.chunks, options.grounding_threshold)
+                } else {
+                    r.stream
+                };
+                Ok(SearchResult {
+                    query,
+                    expanded_queries,
+                    hypothetical_answer: hypothetical,
+                    best_score: best,
+                    query_embedding,
+                    stream,
+                    ..r
+                })
+            }
             Err(e) => Err(anyhow!(e.to_string())),
         }
     }
-}
\ No newline at end of file
+
+    async fn embed_queries(&self, queries: Vec<String>) -> Result<Vec<EmbeddingVector>> {
+        let count = queries.len();
+        let emb_query = GenerateEmbeddingsRequest::new(
+            "bge-m3".to_owned(), 
+            EmbeddingsInput::Multiple(queries)
+        );
+        let embeddings = match self.ollama.embed(emb_query).await {
+            Ok(resp) => resp.embeddings,
+            Err(e) => return Err(anyhow!(format!("Failed embedding the query: {}", e))),
+        };
+        if embeddings.len() != count {
+            return Err(anyhow!("Expected {} query embeddings, got {}", count, embeddings.len()));
+        }
+        Ok(embeddings.into_iter().map(EmbeddingVector).collect())
+    }
+
+    /// Runs the dense search and, when enabled, the BM25 keyword search for the query.
+    ///
+    /// With both rankings available they are fused with weighted reciprocal rank fusion and cut
+    /// back to the candidate limit, otherwise the dense results are returned as they are.
+    async fn retrieve(&self, query: &str, embedding: EmbeddingVector, options: &SearchOptions) -> Result<Vec<ResultChunk>> {
+        let dense: Vec<ResultChunk> = vector_search(embedding, options)
+            .await?
+            .result
+            .into_iter()
+            .map(|p| p.into())
+            .collect();
+
+        let keywords = KeywordVector::from_query(query);
+        if options.keyword_weight <= 0.0 || keywords.is_empty() || !keyword_search_enabled() {
+            return Ok(dense);
+        }
+
+        let keyword: Vec<ResultChunk> = keyword_search(keywords, options)
+            .await?
+            .result
+            .into_iter()
+            .map(|p| {
+                let mut chunk: ResultChunk = p.into();
+                chunk.keyword_score = Some(chunk.score);
+                chunk.score = 0.0;
+                chunk
+            })
+            .collect();
+
+        let mut fused = reciprocal_rank_fusion(vec![
+            (dense, options.dense_weight),
+            (keyword, options.keyword_weight),
+        ]);
+        fused.truncate(options.candidate_limit() as usize);
+        Ok(fused)
+    }
+}
+
+fn is_legacy_doc_id(doc_id: &str, original_name: &str) -> bool {
+    let Some(prefix) = doc_id.strip_suffix(original_name).and_then(|p| p.strip_suffix('_')) else {
+        return false;
+    };
+    !prefix.is_empty() && (prefix.chars().all(|c| c.is_ascii_digit()) || uuid::Uuid::parse_str(prefix).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_legacy_doc_ids() {
+        assert!(is_legacy_doc_id("12_guide.pdf", "guide.pdf"));
+        assert!(is_legacy_doc_id("0b0f8a4e-5d55-4c5e-9a51-2b1f0f7d2d11_guide.pdf", "guide.pdf"));
+        assert!(!is_legacy_doc_id("guide.pdf", "guide.pdf"));
+        assert!(!is_legacy_doc_id("_guide.pdf", "guide.pdf"));
+        assert!(!is_legacy_doc_id("erasmus_guide.pdf", "guide.pdf"));
+        assert!(!is_legacy_doc_id("12_other.pdf", "guide.pdf"));
+    }
+}
@@ -3,7 +3,7 @@ use anyhow::{Result, anyhow};
 use serde_json::Value;
 use crate::rag::comm::embedding::{Embeddable, EmbeddingVector};
 
-use super::embedded_chunk::EmbeddedChunk;
+use super::embedded_chunk::{point_id, DocumentMetadata, EmbeddedChunk};
 
 
 #[derive(Debug)]
@@ -25,22 +25,19 @@ impl Embeddable for Chunk {
         self.embedding_vector = Some(embedding_vectors[0].clone());
     }
     
-    fn prepare_for_upload(self, doc_id: String, doc_summary: Option<String>, _tags: Vec<String>) -> Result<Vec<EmbeddedChunk>> {
+    fn prepare_for_upload(self, document: &DocumentMetadata) -> Result<Vec<EmbeddedChunk>> {
         let embedding_vector = match self.embedding_vector {
             Some(v) => v,
             None => return Err(anyhow!("No embedding vector on chunk")),
         };
-        let doc_summary = if let Some(summ) = doc_summary {
-            summ
-        } else {
-            "".to_string()
-        };
         Ok(vec![EmbeddedChunk {
-            embedding_vector,id:uuid::Uuid::new_v4().to_string(),
-            doc_id,doc_seq_num: self.seq_num,
+            embedding_vector,
+            id: point_id(&document.doc_id, self.seq_num, 0),
+            doc_seq_num: self.seq_num,
+            question_index: 0,
             content: self.text,
             additional_data: Value::Null, 
-            doc_summary,
+            document: document.clone(),
         }])
     }
 
@@ -1,29 +1,47 @@
-use qdrant_client::qdrant::PointStruct;
-use serde_json::{Map, Value};
+use chrono::{DateTime, Utc};
+use qdrant_client::qdrant::{NamedVectors, PointStruct, Vector};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
 
-use crate::rag::comm::embedding::EmbeddingVector;
+use crate::rag::{comm::embedding::{EmbeddingVector, KeywordVector, KEYWORD_VECTOR_NAME}, RagProcessableFileType};
 
 
+/// Derives a stable point id from the identity of a chunk.
+///
+/// The same document, chunk sequence number and HyPE question index always map to the
+/// same id, so re-ingesting an unchanged file overwrites its points instead of duplicating them.
+pub fn point_id(doc_id: &str, doc_seq_num: i32, question_index: usize) -> String {
+    let name = format!("urska://{}/{}/{}", doc_id, doc_seq_num, question_index);
+    Uuid::new_v5(&Uuid::NAMESPACE_URL, name.as_bytes()).to_string()
+}
+
+/// Document level fields stored in the payload of every point of a document.
+#[derive(Debug, Clone)]
+pub struct DocumentMetadata {
+    pub doc_id: String,
+    pub doc_summary: String,
+    pub content_hash: String,
+    pub tags: Vec<String>,
+    pub file_type: RagProcessableFileType,
+    pub original_name: String,
+    pub ingested_at: DateTime<Utc>,
+}
+
 #[derive(Debug)]
 pub struct EmbeddedChunk {
     pub embedding_vector: EmbeddingVector,
     pub id: String,
-    pub doc_id: String,
     pub doc_seq_num: i32,
-    pub doc_summary: String,
+    /// Index of the HyPE question the point was embedded from, `0` for plain chunks.
+    pub question_index: usize,
     pub content: String,
     pub additional_data: Value,
+    pub document: DocumentMetadata,
 }
 
 impl Into<PointStruct> for EmbeddedChunk {
     fn into(self) -> PointStruct {
-        let mut payload = Map::new();
-        payload.insert("doc_id".to_string(), Value::String(self.doc_id));
-        payload.insert("doc_seq_num".to_string(), Value::Number(self.doc_seq_num.into()));
-        payload.insert("doc_summary".to_string(), Value::String(self.doc_summary));
-        payload.insert("content".to_string(), Value::String(self.content));
-        payload.insert("additional_data".to_string(), self.additional_data);
-
+        let payload = self.payload();
         PointStruct::new(
             self.id,
             self.embedding_vector.0,
@@ -31,3 +49,41 @@ impl Into<PointStruct> for EmbeddedChunk {
         )
     }
 }
+
+impl EmbeddedChunk {
+    /// Builds a point carrying the dense embedding and, on the first point of a chunk, the
+    /// keyword vector of the chunk content.
+    ///
+    /// The other HyPE points of the chunk get no keyword vector, so a keyword search finds
+    /// every chunk once and each chunk counts once in the IDF.
+    pub fn into_hybrid_point(self) -> PointStruct {
+        let payload = self.payload();
+        let mut vectors = NamedVectors::default().add_vector("", self.embedding_vector.0);
+        if self.question_index == 0 {
+            let keywords = KeywordVector::from_document(&self.content);
+            vectors = vectors.add_vector(KEYWORD_VECTOR_NAME, Vector::new_sparse(keywords.indices, keywords.values));
+        }
+
+        PointStruct::new(
+            self.id,
+            vectors,
+            payload,
+        )
+    }
+
+    fn payload(&self) -> Map<String, Value> {
+        let document = &self.document;
+        let mut payload = Map::new();
+        payload.insert("doc_id".to_string(), Value::String(document.doc_id.clone()));
+        payload.insert("doc_seq_num".to_string(), Value::Number(self.doc_seq_num.into()));
+        payload.insert("doc_summary".to_string(), Value::String(document.doc_summary.clone()));
+        payload.insert("content_hash".to_string(), Value::String(document.content_hash.clone()));
+        payload.insert("tags".to_string(), json!(document.tags));
+        payload.insert("file_type".to_string(), Value::String(document.file_type.as_payload().to_string()));
+        payload.insert("original_name".to_string(), Value::String(document.original_name.clone()));
+        payload.insert("ingested_at".to_string(), Value::String(document.ingested_at.to_rfc3339()));
+        payload.insert("content".to_string(), Value::String(self.content.clone()));
+        payload.insert("additional_data".to_string(), self.additional_data.clone());
+        payload
+    }
+}
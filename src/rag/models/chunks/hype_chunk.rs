@@ -4,7 +4,7 @@ use anyhow::{Result, anyhow};
 use serde_json::{json, Value};
 use crate::rag::comm::{embedding::{Embeddable, EmbeddingVector}, question::Question, OllamaClient};
 
-use super::{chunk::Chunk, embedded_chunk::EmbeddedChunk};
+use super::{chunk::Chunk, embedded_chunk::{point_id, DocumentMetadata, EmbeddedChunk}};
 
 #[derive(Debug)]
 pub struct HypeChunk {
@@ -44,7 +44,7 @@ impl Embeddable for HypeChunk {
         self.embedding_vector = Some(embedding_vector);
     }
     
-    fn prepare_for_upload(self, parent_doc: String, doc_summary: Option<String>, tags: Vec<String>) -> Result<Vec<EmbeddedChunk>> {
+    fn prepare_for_upload(self, document: &DocumentMetadata) -> Result<Vec<EmbeddedChunk>> {
         let embedding_vectors = match self.embedding_vector {
             Some(v) => v,
             None => return Err(anyhow!("No embedding vectors on hype chunk")),
@@ -61,24 +61,17 @@ impl Embeddable for HypeChunk {
             .collect();
 
         let mut embedded_chunks = vec![];
-        let doc_summary = if let Some(summ) = doc_summary {
-            summ
-        } else {
-            "".to_string()
-        };
-
-        
-        for (question, embedding_vector) in questions_with_embeddings.into_iter() {
-            let mut ins: Vec<String> = tags.iter().cloned().collect();
+        for (question_index, (question, embedding_vector)) in questions_with_embeddings.into_iter().enumerate() {
+            let mut ins: Vec<String> = document.tags.iter().cloned().collect();
             ins.insert(0, question.to_string());
             embedded_chunks.push(EmbeddedChunk {
                 embedding_vector,
-                id: uuid::Uuid::new_v4().to_string(),
-                doc_id: parent_doc.clone(),
+                id: point_id(&document.doc_id, self.seq_num, question_index),
                 doc_seq_num: self.seq_num,
+                question_index,
                 content: self.text.clone(),
                 additional_data: json!(ins),
-                doc_summary: doc_summary.clone()
+                document: document.clone(),
             });
         }
 
@@ -8,4 +8,4 @@ mod embedded_chunk;
 pub use chunk::Chunk;
 pub use hype_chunk::HypeChunk;
 pub use result_chunk::ResultChunk;
-pub use embedded_chunk::EmbeddedChunk;
\ No newline at end of file
+pub use embedded_chunk::{DocumentMetadata, EmbeddedChunk};
\ No newline at end of file
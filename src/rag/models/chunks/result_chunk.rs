@@ -1,8 +1,10 @@
-use qdrant_client::qdrant::ScoredPoint;
+use std::collections::HashMap;
+
+use qdrant_client::qdrant::{point_id::PointIdOptions, vector_output::Vector, PointId, RetrievedPoint, ScoredPoint, Value as QdrantValue, VectorsOutput};
 use serde::Serialize;
 use serde_json::Value;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ResultChunk {
     pub id: String,
     pub doc_id: String,
@@ -10,55 +12,111 @@ pub struct ResultChunk {
     pub content: String,
     pub additional_data: Value,
     pub doc_summary: String,
+    pub original_name: String,
+    pub tags: Vec<String>,
     pub score: f32,
+    /// BM25 score of the chunk when it was found by the keyword search.
+    pub keyword_score: Option<f32>,
+    /// Reciprocal rank fusion score of the chunk in a hybrid search.
+    pub fusion_score: Option<f32>,
+    /// Relevance score from the reranker, `0..=1`, when reranking was requested.
+    pub rerank_score: Option<f32>,
+    /// The chunk stitched together with its neighbouring chunks, used instead of `content` in the prompt.
+    pub passage: Option<String>,
+    /// First and last `doc_seq_num` covered by `passage`.
+    pub passage_range: Option<(i32, i32)>,
+    /// Dense vector of the point, only present when it was requested from Qdrant.
+    #[serde(skip)]
+    pub vector: Option<Vec<f32>>,
 }
 
 impl From<ScoredPoint> for ResultChunk {
     fn from(value: ScoredPoint) -> Self {
-        let id: String = match value.id {
-            Some(d) => format!("{:?}", d),
+        let mut chunk = Self::from_payload(value.id, &value.payload, value.score);
+        chunk.vector = dense_vector(value.vectors);
+        chunk
+    }
+}
+
+impl From<RetrievedPoint> for ResultChunk {
+    fn from(value: RetrievedPoint) -> Self {
+        Self::from_payload(value.id, &value.payload, 0.0)
+    }
+}
+
+impl ResultChunk {
+    fn from_payload(id: Option<PointId>, payload: &HashMap<String, QdrantValue>, score: f32) -> Self {
+        let id: String = match id.and_then(|d| d.point_id_options) {
+            Some(PointIdOptions::Uuid(uuid)) => uuid,
+            Some(PointIdOptions::Num(num)) => num.to_string(),
             None => "Unknown".into(),
         };
 
-        let doc_id = match value.payload.get("doc_id") {
+        let doc_id = match payload.get("doc_id") {
             Some(d) => d.as_str().map_or("Unknown", |v| v),
             None => "Unknown",
         };
         let doc_id = doc_id.to_string();
 
-        let doc_seq_num = match value.payload.get("doc_seq_num") {
+        let doc_seq_num = match payload.get("doc_seq_num") {
             Some(d) => d.as_integer().unwrap_or(-1) as i32,
             None => -1,
         };
 
-        let content: String = match value.payload.get("content") {
+        let content: String = match payload.get("content") {
             Some(d) => d.as_str().map_or("".into(), |v| v.into()),
             None => "".into(),
         };
 
-        let additional_data = match value.payload.get("additional_data") {
+        let additional_data = match payload.get("additional_data") {
             Some(d) => d.to_owned(),
             None => Value::Null.into(),
         };
 
-        let doc_summary: String = match value.payload.get("doc_summary") {
+        let doc_summary: String = match payload.get("doc_summary") {
             Some(d) => d.as_str().map_or("".into(), |v| v.into()),
             None => "".into(),
         };      
 
+        let original_name: String = match payload.get("original_name") {
+            Some(d) => d.as_str().map_or("".into(), |v| v.into()),
+            None => "".into(),
+        };
+
+        let tags: Vec<String> = match payload.get("tags").and_then(|d| d.try_list_iter()) {
+            Some(values) => values.filter_map(|v| v.as_str()).map(|v| v.to_string()).collect(),
+            None => vec![],
+        };
+
         Self {
             id,
             doc_id,
             doc_seq_num,
             doc_summary,
+            original_name,
+            tags,
             content,
             additional_data: additional_data.into(),
-            score: value.score,
+            score,
+            keyword_score: None,
+            fusion_score: None,
+            rerank_score: None,
+            passage: None,
+            passage_range: None,
+            vector: None,
         }
     }
-}
 
-impl ResultChunk {
+    /// Link to the source document, the first tag that is a web address.
+    ///
+    /// `/build` tags scraped pages with their URL, other tags are plain labels.
+    pub fn link(&self) -> Option<String> {
+        self.tags
+            .iter()
+            .find(|tag| tag.starts_with("https://") || tag.starts_with("http://"))
+            .cloned()
+    }
+
     pub fn to_prompt_chunk(&self) -> String {
         let link = match &self.additional_data {
             Value::Array(vec) => vec
@@ -75,13 +133,61 @@ impl ResultChunk {
             format!("\tPARENT DOCUMENT ADDITIONAL DATA: {}", link)
         };
         let doc_summary = &self.doc_summary;
+        let content = self.passage.as_ref().unwrap_or(&self.content);
         
         
         format!(
             "CHUNK:\n\tPARENT DOCUMENT DESCRIPTION:\n{}\n{}\n\n\tCHUNK CONTENTS: {}", 
             doc_summary,
             link,
-            self.content
+            content
         )
     }
-}
\ No newline at end of file
+}
+
+/// The unnamed dense vector of a point, for collections with and without the keyword vector.
+fn dense_vector(vectors: Option<VectorsOutput>) -> Option<Vec<f32>> {
+    match vectors?.get_vector_by_name("")? {
+        Vector::Dense(dense) => Some(dense.data),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+impl ResultChunk {
+    /// A chunk with only its identity, content and dense score set.
+    pub fn for_test(id: &str, doc_id: &str, doc_seq_num: i32, score: f32) -> Self {
+        Self {
+            id: id.into(),
+            doc_id: doc_id.into(),
+            doc_seq_num,
+            content: format!("{} chunk {}", doc_id, doc_seq_num),
+            additional_data: Value::Null,
+            doc_summary: String::new(),
+            original_name: doc_id.into(),
+            tags: vec![],
+            score,
+            keyword_score: None,
+            fusion_score: None,
+            rerank_score: None,
+            passage: None,
+            passage_range: None,
+            vector: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_is_the_first_url_tag() {
+        let mut chunk = ResultChunk::for_test("p", "guide.pdf", 0, 0.5);
+        chunk.tags = vec!["erasmus".into(), "fri".into()];
+        assert_eq!(chunk.link(), None);
+
+        chunk.tags.push("https://www.famnit.upr.si/sl/studij".into());
+        assert_eq!(chunk.link().as_deref(), Some("https://www.famnit.upr.si/sl/studij"));
+    }
+}
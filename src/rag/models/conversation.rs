@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// A single message of a chat conversation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub role: Role,
+    pub content: String,
+}
+
+/// Renders the conversation as a transcript for the answer prompt, empty when there is no history.
+pub fn history_prompt(history: &[Turn]) -> String {
+    if history.is_empty() {
+        return "".to_string();
+    }
+
+    let transcript: Vec<String> = history
+        .iter()
+        .map(|t| match t.role {
+            Role::User => format!("User: {}", t.content),
+            Role::Assistant => format!("Assistant: {}", t.content),
+        })
+        .collect();
+
+    format!("Conversation so far:\n{}\n", transcript.join("\n"))
+}
@@ -6,6 +6,8 @@ pub struct ChunkedFile<T> where T: Embeddable {
     pub file_type: RagProcessableFileType,
     pub chunks: Vec<T>,
     pub internal_id: String,
+    pub original_name: String,
+    pub content_hash: String,
     pub original_file_description: Option<String>,
     pub syntetic_file_description: Option<String>,
     pub tags: Option<Vec<String>>,
@@ -22,6 +24,8 @@ where
             file_type: file.file_type, 
             chunks, 
             internal_id: file.internal_id,
+            original_name: file.original_name,
+            content_hash: file.content_hash,
             tags: file.tags,
             original_file_description: file.original_file_description,
             syntetic_file_description: file.syntetic_file_description,
@@ -0,0 +1,423 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use qdrant_client::qdrant::{Condition, DatetimeRange, Filter, Range, Timestamp};
+
+/// Payload fields that can be used in a filter expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterField {
+    Tags,
+    FileType,
+    OriginalName,
+    DocId,
+    DocSeqNum,
+    IngestedAt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    NotEq,
+    Contains,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Keyword(String),
+    Integer(i64),
+    Datetime(DateTime<Utc>),
+}
+
+/// A parsed search filter expression, e.g. `tags contains "erasmus" AND file_type = Pdf`.
+///
+/// Conditions are joined with `AND` and `OR` (`AND` binds tighter) and can be grouped with
+/// parentheses. `ingested_at` and `doc_seq_num` also accept `<`, `<=`, `>` and `>=`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchFilter {
+    And(Vec<SearchFilter>),
+    Or(Vec<SearchFilter>),
+    Condition {
+        field: FilterField,
+        op: FilterOp,
+        value: FilterValue,
+    },
+}
+
+impl FilterField {
+    fn payload_key(&self) -> &'static str {
+        match self {
+            FilterField::Tags => "tags",
+            FilterField::FileType => "file_type",
+            FilterField::OriginalName => "original_name",
+            FilterField::DocId => "doc_id",
+            FilterField::DocSeqNum => "doc_seq_num",
+            FilterField::IngestedAt => "ingested_at",
+        }
+    }
+}
+
+impl FromStr for FilterField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "tags" | "tag" => Ok(FilterField::Tags),
+            "file_type" => Ok(FilterField::FileType),
+            "original_name" => Ok(FilterField::OriginalName),
+            "doc_id" => Ok(FilterField::DocId),
+            "doc_seq_num" => Ok(FilterField::DocSeqNum),
+            "ingested_at" => Ok(FilterField::IngestedAt),
+            _ => Err(anyhow!("Unknown filter field '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(FilterOp),
+    LParen,
+    RParen,
+}
+
+impl FromStr for SearchFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let filter = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(anyhow!("Unexpected {:?} in filter expression", token));
+        }
+        Ok(filter)
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some(ch) if ch == c => break,
+                        Some(ch) => value.push(ch),
+                        None => return Err(anyhow!("Unterminated string in filter expression")),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let followed_by_eq = chars.peek() == Some(&'=');
+                if followed_by_eq {
+                    chars.next();
+                }
+                let op = match (c, followed_by_eq) {
+                    ('=', _) => FilterOp::Eq,
+                    ('!', true) => FilterOp::NotEq,
+                    ('<', false) => FilterOp::Lt,
+                    ('<', true) => FilterOp::Lte,
+                    ('>', false) => FilterOp::Gt,
+                    ('>', true) => FilterOp::Gte,
+                    _ => return Err(anyhow!("Unknown operator '{}' in filter expression", c)),
+                };
+                tokens.push(Token::Op(op));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || "()=!<>\"'".contains(ch) {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                if word.eq_ignore_ascii_case("contains") {
+                    tokens.push(Token::Op(FilterOp::Contains));
+                } else {
+                    tokens.push(Token::Word(word));
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn parse_or(&mut self) -> Result<SearchFilter> {
+        let mut terms = vec![self.parse_and()?];
+        while self.next_is_keyword("or") {
+            self.next();
+            terms.push(self.parse_and()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { SearchFilter::Or(terms) })
+    }
+
+    fn parse_and(&mut self) -> Result<SearchFilter> {
+        let mut terms = vec![self.parse_term()?];
+        while self.next_is_keyword("and") {
+            self.next();
+            terms.push(self.parse_term()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { SearchFilter::And(terms) })
+    }
+
+    fn parse_term(&mut self) -> Result<SearchFilter> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let inner = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(inner),
+                    _ => Err(anyhow!("Missing ')' in filter expression")),
+                }
+            }
+            Some(Token::Word(field)) => {
+                let field: FilterField = field.parse()?;
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    other => return Err(anyhow!("Expected an operator after {:?}, found {:?}", field, other)),
+                };
+                let raw = match self.next() {
+                    Some(Token::Word(v)) | Some(Token::Quoted(v)) => v,
+                    other => return Err(anyhow!("Expected a value after {:?}, found {:?}", op, other)),
+                };
+                condition(field, op, raw)
+            }
+            other => Err(anyhow!("Expected a condition, found {:?}", other)),
+        }
+    }
+}
+
+fn condition(field: FilterField, op: FilterOp, raw: String) -> Result<SearchFilter> {
+    let is_range = matches!(op, FilterOp::Lt | FilterOp::Lte | FilterOp::Gt | FilterOp::Gte);
+    let value = match field {
+        FilterField::DocSeqNum => FilterValue::Integer(raw
+            .parse()
+            .map_err(|_| anyhow!("doc_seq_num expects an integer, got '{}'", raw))?),
+        FilterField::IngestedAt => FilterValue::Datetime(parse_datetime(&raw)?),
+        FilterField::FileType => FilterValue::Keyword(normalize_file_type(&raw)?),
+        _ => FilterValue::Keyword(raw),
+    };
+
+    let supported = match field {
+        FilterField::Tags => !is_range,
+        FilterField::DocSeqNum => op != FilterOp::Contains,
+        FilterField::IngestedAt => is_range,
+        _ => matches!(op, FilterOp::Eq | FilterOp::NotEq),
+    };
+    if !supported {
+        return Err(anyhow!("{} doesn't support the {:?} operator", field.payload_key(), op));
+    }
+
+    Ok(SearchFilter::Condition { field, op, value })
+}
+
+fn parse_datetime(raw: &str) -> Result<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|d| d.and_utc())
+        .ok_or(anyhow!("ingested_at expects an RFC 3339 timestamp or a YYYY-MM-DD date, got '{}'", raw))
+}
+
+fn normalize_file_type(raw: &str) -> Result<String> {
+    match raw.to_lowercase().as_str() {
+        "pdf" => Ok("Pdf".into()),
+        "markdown" | "md" => Ok("Markdown".into()),
+        "text" | "txt" => Ok("Text".into()),
+        _ => Err(anyhow!("Unknown file_type '{}', expected Pdf, Markdown or Text", raw)),
+    }
+}
+
+impl From<&SearchFilter> for Filter {
+    fn from(value: &SearchFilter) -> Self {
+        match value {
+            SearchFilter::And(terms) => Filter::must(terms.iter().map(|t| Filter::from(t).into())),
+            SearchFilter::Or(terms) => Filter::should(terms.iter().map(|t| Filter::from(t).into())),
+            SearchFilter::Condition { .. } => Filter::must([to_condition(value)]),
+        }
+    }
+}
+
+fn to_condition(filter: &SearchFilter) -> Condition {
+    let SearchFilter::Condition { field, op, value } = filter else {
+        return Filter::from(filter).into();
+    };
+    let key = field.payload_key();
+
+    match (op, value) {
+        (FilterOp::NotEq, _) => {
+            let matching = SearchFilter::Condition { field: *field, op: FilterOp::Eq, value: value.clone() };
+            Filter::must_not([to_condition(&matching)]).into()
+        }
+        (FilterOp::Eq | FilterOp::Contains, FilterValue::Keyword(v)) => Condition::matches(key, v.clone()),
+        (FilterOp::Eq | FilterOp::Contains, FilterValue::Integer(v)) => Condition::matches(key, *v),
+        (_, FilterValue::Integer(v)) => {
+            let v = Some(*v as f64);
+            Condition::range(key, match op {
+                FilterOp::Lt => Range { lt: v, ..Default::default() },
+                FilterOp::Lte => Range { lte: v, ..Default::default() },
+                FilterOp::Gt => Range { gt: v, ..Default::default() },
+                _ => Range { gte: v, ..Default::default() },
+            })
+        }
+        (_, FilterValue::Datetime(v)) => {
+            let v = Some(Timestamp { seconds: v.timestamp(), nanos: v.timestamp_subsec_nanos() as i32 });
+            Condition::datetime_range(key, match op {
+                FilterOp::Lt => DatetimeRange { lt: v, ..Default::default() },
+                FilterOp::Lte => DatetimeRange { lte: v, ..Default::default() },
+                FilterOp::Gt => DatetimeRange { gt: v, ..Default::default() },
+                _ => DatetimeRange { gte: v, ..Default::default() },
+            })
+        }
+        (_, FilterValue::Keyword(v)) => Condition::matches(key, v.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use qdrant_client::qdrant::condition::ConditionOneOf;
+
+    use super::*;
+
+    fn parse(input: &str) -> SearchFilter {
+        input.parse().unwrap()
+    }
+
+    fn keyword(field: FilterField, op: FilterOp, value: &str) -> SearchFilter {
+        SearchFilter::Condition { field, op, value: FilterValue::Keyword(value.into()) }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let filter = parse("tags contains a OR tags contains b AND file_type = pdf");
+        assert_eq!(filter, SearchFilter::Or(vec![
+            keyword(FilterField::Tags, FilterOp::Contains, "a"),
+            SearchFilter::And(vec![
+                keyword(FilterField::Tags, FilterOp::Contains, "b"),
+                keyword(FilterField::FileType, FilterOp::Eq, "Pdf"),
+            ]),
+        ]));
+    }
+
+    #[test]
+    fn parentheses_group_conditions() {
+        let filter = parse("(tags contains a or tags contains b) and file_type = md");
+        assert_eq!(filter, SearchFilter::And(vec![
+            SearchFilter::Or(vec![
+                keyword(FilterField::Tags, FilterOp::Contains, "a"),
+                keyword(FilterField::Tags, FilterOp::Contains, "b"),
+            ]),
+            keyword(FilterField::FileType, FilterOp::Eq, "Markdown"),
+        ]));
+        assert!("(tags contains a".parse::<SearchFilter>().is_err());
+        assert!("tags contains a)".parse::<SearchFilter>().is_err());
+    }
+
+    #[test]
+    fn accepts_both_quote_styles() {
+        assert_eq!(parse(r#"original_name = "study guide.pdf""#), keyword(FilterField::OriginalName, FilterOp::Eq, "study guide.pdf"));
+        assert_eq!(parse("original_name = 'study guide.pdf'"), keyword(FilterField::OriginalName, FilterOp::Eq, "study guide.pdf"));
+        assert_eq!(parse(r#"tags contains 'say "hi"'"#), keyword(FilterField::Tags, FilterOp::Contains, r#"say "hi""#));
+    }
+
+    #[test]
+    fn rejects_unterminated_strings() {
+        assert!(r#"tags contains "erasmus"#.parse::<SearchFilter>().is_err());
+        assert!("tags contains 'erasmus".parse::<SearchFilter>().is_err());
+    }
+
+    #[test]
+    fn not_equal_maps_to_must_not() {
+        let filter = Filter::from(&parse("file_type != text"));
+        let Some(ConditionOneOf::Filter(inner)) = &filter.must[0].condition_one_of else {
+            panic!("expected a nested filter, got {:?}", filter);
+        };
+        assert!(inner.must.is_empty());
+        assert_eq!(inner.must_not.len(), 1);
+    }
+
+    #[test]
+    fn rejects_unsupported_operators() {
+        for input in [
+            "tags < a",
+            "file_type contains pdf",
+            "original_name > a",
+            "doc_id >= a",
+            "doc_seq_num contains 3",
+            "ingested_at = 2024-01-01",
+            "file_type = docx",
+            "doc_seq_num > three",
+            "author = me",
+        ] {
+            assert!(input.parse::<SearchFilter>().is_err(), "{} should be rejected", input);
+        }
+    }
+
+    #[test]
+    fn parses_ingested_at_dates() {
+        let date = parse("ingested_at >= 2024-03-01");
+        let expected = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        assert_eq!(date, SearchFilter::Condition {
+            field: FilterField::IngestedAt,
+            op: FilterOp::Gte,
+            value: FilterValue::Datetime(expected),
+        });
+
+        let timestamp = parse("ingested_at < '2024-03-01T12:30:00+02:00'");
+        let expected = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(10, 30, 0).unwrap().and_utc();
+        assert_eq!(timestamp, SearchFilter::Condition {
+            field: FilterField::IngestedAt,
+            op: FilterOp::Lt,
+            value: FilterValue::Datetime(expected),
+        });
+
+        assert!("ingested_at > 01.03.2024".parse::<SearchFilter>().is_err());
+    }
+}
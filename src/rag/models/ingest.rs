@@ -0,0 +1,12 @@
+use serde::Serialize;
+
+/// Stages of the ingestion pipeline run by `Rag::insert`.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestStage {
+    Loading,
+    Chunking,
+    Hype,
+    Embedding,
+    Upserting,
+}
@@ -1,7 +1,9 @@
-use std::path::PathBuf;
+use std::{env, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
+use super::SearchFilter;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RagProcessableFileType {
     Text,
@@ -9,6 +11,17 @@ pub enum RagProcessableFileType {
     Pdf,
 }
 
+impl RagProcessableFileType {
+    /// Value stored in the `file_type` payload field.
+    pub fn as_payload(&self) -> &'static str {
+        match self {
+            RagProcessableFileType::Text => "Text",
+            RagProcessableFileType::Markdown => "Markdown",
+            RagProcessableFileType::Pdf => "Pdf",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RagProcessableFile {
     pub path: PathBuf,
@@ -19,3 +32,83 @@ pub struct RagProcessableFile {
     pub tags: Option<Vec<String>>,
 }
 
+/// Retrieval parameters of a single search.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Number of points requested from Qdrant.
+    pub top_k: u64,
+    /// Points scoring below this are not returned.
+    pub score_threshold: Option<f32>,
+    /// When not empty, only points carrying at least one of these tags are returned.
+    pub tags: Vec<String>,
+    /// Additional payload filter the points have to match.
+    pub filter: Option<SearchFilter>,
+    /// Weight of the dense (embedding) ranking in the reciprocal rank fusion.
+    pub dense_weight: f32,
+    /// Weight of the BM25 keyword ranking in the reciprocal rank fusion, `0` disables keyword search.
+    pub keyword_weight: f32,
+    /// When set, the retrieved chunks are reranked and only this many of the best ones are kept.
+    pub rerank_top_n: Option<usize>,
+    /// Number of neighbouring chunks on each side of a hit that are added to its passage.
+    pub neighbours: i32,
+    /// When set, the chunks are picked with maximal marginal relevance, `1` is pure relevance and `0` pure diversity.
+    pub mmr_lambda: Option<f32>,
+    /// Number of query paraphrases or sub-questions searched for in addition to the query.
+    pub query_expansions: usize,
+    /// What the query is embedded as for the dense search.
+    pub query_mode: QueryMode,
+    /// Check the answer's claims against the retrieved chunks once it is generated.
+    pub verify_grounding: bool,
+    /// Answers with a grounding score below this are withheld, implies `verify_grounding`.
+    pub grounding_threshold: Option<f32>,
+    /// When the best dense score is below this, no answer is generated and a fallback is sent instead.
+    /// Defaults to `MIN_RELEVANCE_SCORE`.
+    pub min_relevance: Option<f32>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            top_k: 10,
+            score_threshold: None,
+            tags: vec![],
+            filter: None,
+            dense_weight: 1.0,
+            keyword_weight: 1.0,
+            rerank_top_n: None,
+            neighbours: 0,
+            mmr_lambda: None,
+            query_expansions: 0,
+            query_mode: QueryMode::Question,
+            verify_grounding: false,
+            grounding_threshold: None,
+            min_relevance: env::var("MIN_RELEVANCE_SCORE").ok().and_then(|s| s.parse().ok()),
+        }
+    }
+}
+
+/// Text whose embedding is used for the dense search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMode {
+    /// The query itself, matched against the stored chunks and HyPE questions.
+    #[default]
+    Question,
+    /// A hypothetical answer drafted by the LLM (HyDE).
+    Hyde,
+    /// The average of the query and the hypothetical answer embeddings.
+    HydeAverage,
+}
+
+/// How many more candidates than `top_k` are retrieved for maximal marginal relevance to choose from.
+const MMR_CANDIDATE_FACTOR: u64 = 3;
+
+impl SearchOptions {
+    /// Number of points requested from Qdrant per ranking.
+    pub fn candidate_limit(&self) -> u64 {
+        match self.mmr_lambda {
+            Some(_) => self.top_k * MMR_CANDIDATE_FACTOR,
+            None => self.top_k,
+        }
+    }
+}
@@ -2,7 +2,13 @@ pub mod chunks;
 mod files;
 mod output;
 mod input;
+mod filter;
+mod ingest;
+mod conversation;
 
 pub use files::chunked_file::ChunkedFile;
-pub use output::SearchResult;
-pub use input::{RagProcessableFile, RagProcessableFileType};
\ No newline at end of file
+pub use output::{Citation, DocumentChunk, DroppedChunk, Grounding, SearchEvent, SearchEventStream, DocumentOverview, SearchResult};
+pub use filter::SearchFilter;
+pub use ingest::IngestStage;
+pub use conversation::{history_prompt, Role, Turn};
+pub use input::{QueryMode, RagProcessableFile, RagProcessableFileType, SearchOptions};
\ No newline at end of file
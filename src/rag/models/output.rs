@@ -1,9 +1,99 @@
-use ollama_rs::generation::completion::GenerationResponseStream;
+use std::pin::Pin;
+
+use serde::Serialize;
+use tokio_stream::Stream;
 
 use crate::rag::models::chunks::ResultChunk;
 
 
 pub struct SearchResult {
+    /// The query used for retrieval, the message rewritten with its conversation for follow-ups.
+    pub query: String,
+    /// Additional queries the search was expanded with.
+    pub expanded_queries: Vec<String>,
+    /// The hypothetical answer the query was embedded as in the HyDE modes.
+    pub hypothetical_answer: Option<String>,
+    /// Whether retrieval was too weak to answer and the canned fallback answer was sent.
+    pub fallback: bool,
+    /// Titles of the nearest documents, suggested with the fallback answer.
+    pub suggestions: Vec<String>,
+    /// Best dense score of the retrieved chunks, before the fallback gate.
+    pub best_score: Option<f32>,
+    /// Embedding of the (rewritten) query.
+    pub query_embedding: Vec<f32>,
     pub chunks: Vec<ResultChunk>,
-    pub stream: GenerationResponseStream,
-}
\ No newline at end of file
+    /// Retrieved chunks left out of the prompt because they didn't fit the context budget.
+    pub dropped: Vec<DroppedChunk>,
+    pub stream: SearchEventStream,
+}
+
+/// Events of a search answer as it is being generated.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchEvent {
+    /// Next piece of the answer text.
+    Delta { text: String },
+    /// The sources cited in the answer, sent once the answer is complete.
+    Citations { citations: Vec<Citation> },
+    /// Follow-up questions suggested by the model, sent once the answer is complete.
+    Followups { questions: Vec<String> },
+    /// Result of checking the answer against the context, sent before `Done` when requested.
+    Grounding { grounding: Grounding },
+    /// The generation failed, no further events follow.
+    Error { message: String },
+    /// The answer is complete, with the generation statistics reported by Ollama.
+    Done {
+        prompt_tokens: Option<u16>,
+        answer_tokens: Option<u16>,
+        generation_ms: Option<u64>,
+    },
+}
+
+pub type SearchEventStream = Pin<Box<dyn Stream<Item = SearchEvent> + Send>>;
+
+/// Links an `[n]` marker in the answer to the chunk it cites.
+#[derive(Debug, Clone, Serialize)]
+pub struct Citation {
+    pub marker: usize,
+    pub chunk_id: String,
+    pub doc_id: String,
+    /// Link to the source document, when the chunk carries one.
+    pub link: Option<String>,
+}
+
+/// How well the answer is supported by the retrieved chunks.
+#[derive(Debug, Clone, Serialize)]
+pub struct Grounding {
+    /// Share of the answer's claims supported by the context, `0..=1`.
+    pub score: f32,
+    pub unsupported_claims: Vec<String>,
+    /// Whether the answer was withheld for scoring below the requested threshold.
+    pub suppressed: bool,
+}
+
+/// A retrieved chunk that was left out of the prompt to stay within the model's context budget.
+#[derive(Debug, Serialize)]
+pub struct DroppedChunk {
+    pub id: String,
+    pub doc_id: String,
+    pub doc_seq_num: i32,
+    pub estimated_tokens: usize,
+}
+
+/// Summary of a single ingested document, aggregated from its points.
+#[derive(Debug, Serialize)]
+pub struct DocumentOverview {
+    pub doc_id: String,
+    pub doc_summary: String,
+    pub tags: Vec<String>,
+    pub chunk_count: usize,
+    pub question_count: usize,
+}
+
+/// A chunk of a document together with the HyPE questions generated for it.
+#[derive(Debug, Serialize)]
+pub struct DocumentChunk {
+    pub doc_seq_num: i32,
+    pub content: String,
+    pub questions: Vec<String>,
+}
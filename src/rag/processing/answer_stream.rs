@@ -0,0 +1,289 @@
+use std::collections::VecDeque;
+
+use futures::stream;
+use ollama_rs::generation::completion::GenerationResponseStream;
+use tokio_stream::StreamExt;
+
+use crate::rag::models::{Citation, SearchEvent, SearchEventStream};
+
+use super::{citations::citation_map, recursive_prompt::TestFormat};
+
+/// Turns the raw generation stream into typed search events.
+///
+/// For `structured` answers (JSON following `TestFormat`) the text of the `resp` field is
+/// emitted as deltas while it is being generated and the parsed follow-up `questions` once
+/// the generation is complete. Plain answers are passed through as deltas. Once complete,
+/// the `sources` cited in the answer are sent as well. A completed generation ends the
+/// stream with a done event, a failing one with an error event.
+pub fn answer_events(generation: GenerationResponseStream, structured: bool, sources: Vec<Citation>) -> SearchEventStream {
+    let state = AnswerState {
+        generation: Some(generation),
+        parser: AnswerParser { structured, raw: String::new(), answer: String::new(), emitted: 0, sources },
+        pending: VecDeque::new(),
+        done: None,
+    };
+
+    Box::pin(stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((event, state));
+            }
+
+            match state.generation.as_mut()?.next().await {
+                Some(Ok(responses)) => {
+                    for resp in responses {
+                        let events = state.parser.push(&resp.response);
+                        state.pending.extend(events);
+                        if resp.done {
+                            state.done = Some(SearchEvent::Done {
+                                prompt_tokens: resp.prompt_eval_count,
+                                answer_tokens: resp.eval_count,
+                                generation_ms: resp.total_duration.map(|d| d / 1_000_000),
+                            });
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    state.generation = None;
+                    state.pending.push_back(SearchEvent::Error { message: e.to_string() });
+                }
+                None => {
+                    state.generation = None;
+                    let events = state.parser.finish();
+                    state.pending.extend(events);
+                    state.pending.push_back(state.done.take().unwrap_or(SearchEvent::Done {
+                        prompt_tokens: None,
+                        answer_tokens: None,
+                        generation_ms: None,
+                    }));
+                }
+            }
+        }
+    }))
+}
+
+struct AnswerState {
+    generation: Option<GenerationResponseStream>,
+    parser: AnswerParser,
+    pending: VecDeque<SearchEvent>,
+    /// Done event built from the final generation response, sent after the parsed answer.
+    done: Option<SearchEvent>,
+}
+
+struct AnswerParser {
+    structured: bool,
+    /// Everything generated so far.
+    raw: String,
+    /// Answer text sent as deltas so far.
+    answer: String,
+    /// Number of answer characters already sent as deltas.
+    emitted: usize,
+    /// Citations of the context chunks, by the marker they were labelled with in the prompt.
+    sources: Vec<Citation>,
+}
+
+impl AnswerParser {
+    fn push(&mut self, token: &str) -> Vec<SearchEvent> {
+        if !self.structured {
+            self.answer.push_str(token);
+            return delta(token.to_string()).into_iter().collect();
+        }
+
+        self.raw.push_str(token);
+        match partial_string_field(&self.raw, "resp") {
+            Some(answer) => self.delta_from(&answer).into_iter().collect(),
+            None => vec![],
+        }
+    }
+
+    fn finish(&mut self) -> Vec<SearchEvent> {
+        if !self.structured {
+            return vec![self.citations()];
+        }
+
+        match serde_json::from_str::<TestFormat>(&self.raw) {
+            Ok(format) => {
+                let mut events: Vec<SearchEvent> = self.delta_from(&format.resp).into_iter().collect();
+                events.push(self.citations());
+                events.push(SearchEvent::Followups { questions: format.questions });
+                events
+            }
+            Err(e) => {
+                eprintln!("Failed parsing the structured answer: {}", e);
+                let mut events = vec![];
+                if self.emitted == 0 {
+                    let raw = self.raw.clone();
+                    self.answer.push_str(&raw);
+                    events.extend(delta(raw));
+                }
+                events.push(self.citations());
+                events
+            }
+        }
+    }
+
+    fn citations(&self) -> SearchEvent {
+        SearchEvent::Citations { citations: citation_map(&self.answer, &self.sources) }
+    }
+
+    fn delta_from(&mut self, answer: &str) -> Option<SearchEvent> {
+        let text: String = answer.chars().skip(self.emitted).collect();
+        self.emitted += text.chars().count();
+        self.answer.push_str(&text);
+        delta(text)
+    }
+}
+
+fn delta(text: String) -> Option<SearchEvent> {
+    if text.is_empty() {
+        None
+    } else {
+        Some(SearchEvent::Delta { text })
+    }
+}
+
+/// Decodes as much of the string value of `"key"` as has been generated in the (incomplete) JSON `raw`.
+///
+/// Returns `None` until the opening quote of the value is seen. An escape sequence cut off at
+/// the end of `raw` is left out until the rest of it arrives.
+fn partial_string_field(raw: &str, key: &str) -> Option<String> {
+    let pattern = format!("\"{}\"", key);
+    let mut search_from = 0;
+
+    let value_start = loop {
+        let key_at = search_from + raw[search_from..].find(&pattern)?;
+        let rest = raw[key_at + pattern.len()..].trim_start();
+        if let Some(value) = rest.strip_prefix(':').map(|r| r.trim_start()) {
+            if let Some(value) = value.strip_prefix('"') {
+                break value;
+            }
+            if !value.is_empty() {
+                search_from = key_at + pattern.len();
+                continue;
+            }
+        }
+        return None;
+    };
+
+    let mut decoded = String::new();
+    let mut chars = value_start.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => {
+                let Some(escaped) = chars.next() else { break };
+                match escaped {
+                    'n' => decoded.push('\n'),
+                    't' => decoded.push('\t'),
+                    'r' => decoded.push('\r'),
+                    'b' => decoded.push('\u{8}'),
+                    'f' => decoded.push('\u{c}'),
+                    'u' => {
+                        let Some(code) = read_hex(&mut chars) else { break };
+                        if (0xD800..0xDC00).contains(&code) {
+                            // High surrogate, the low one follows as another \uXXXX.
+                            if chars.next() != Some('\\') || chars.next() != Some('u') {
+                                break;
+                            }
+                            let Some(low) = read_hex(&mut chars) else { break };
+                            let combined = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            decoded.push(char::from_u32(combined).unwrap_or('\u{FFFD}'));
+                        } else {
+                            decoded.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                        }
+                    }
+                    other => decoded.push(other),
+                }
+            }
+            c => decoded.push(c),
+        }
+    }
+
+    Some(decoded)
+}
+
+fn read_hex<I>(chars: &mut I) -> Option<u32> where I: Iterator<Item = char> {
+    let hex: String = chars.take(4).collect();
+    if hex.len() < 4 {
+        return None;
+    }
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser() -> AnswerParser {
+        AnswerParser { structured: true, raw: String::new(), answer: String::new(), emitted: 0, sources: vec![] }
+    }
+
+    fn deltas(events: &[SearchEvent]) -> String {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                SearchEvent::Delta { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        let raw = r#"{"resp": "say \"hi\" to C:\\temp\nnow"#;
+        assert_eq!(partial_string_field(raw, "resp").unwrap(), "say \"hi\" to C:\\temp\nnow");
+    }
+
+    #[test]
+    fn decodes_unicode_escapes() {
+        let raw = r#"{"resp": "Ur\u0161ka \ud83c\udf93", "questions": []}"#;
+        assert_eq!(partial_string_field(raw, "resp").unwrap(), "Urška 🎓");
+    }
+
+    #[test]
+    fn leaves_out_cut_off_escapes() {
+        assert_eq!(partial_string_field(r#"{"resp": "a\"#, "resp").unwrap(), "a");
+        assert_eq!(partial_string_field(r#"{"resp": "a\u01"#, "resp").unwrap(), "a");
+        assert_eq!(partial_string_field(r#"{"resp": "a\ud83c"#, "resp").unwrap(), "a");
+    }
+
+    #[test]
+    fn waits_for_the_opening_quote() {
+        assert_eq!(partial_string_field(r#"{"re"#, "resp"), None);
+        assert_eq!(partial_string_field(r#"{"resp""#, "resp"), None);
+        assert_eq!(partial_string_field(r#"{"resp": "#, "resp"), None);
+        assert_eq!(partial_string_field(r#"{"resp": ""#, "resp").unwrap(), "");
+    }
+
+    #[test]
+    fn skips_the_key_inside_other_values() {
+        let raw = r#"{"questions": ["\"resp\""], "resp": "answer"#;
+        assert_eq!(partial_string_field(raw, "resp").unwrap(), "answer");
+    }
+
+    #[test]
+    fn streams_a_field_split_across_deltas() {
+        let mut parser = parser();
+        let tokens = [r#"{"re"#, r#"sp": "Ur\"#, r#"u016"#, r#"1ka \""#, r#"hi\"", "#, r#""questions": ["Why?"]}"#];
+        let mut events = vec![];
+        for token in tokens {
+            events.extend(parser.push(token));
+        }
+        assert_eq!(deltas(&events), "Urška \"hi\"");
+
+        let finished = parser.finish();
+        assert_eq!(deltas(&finished), "");
+        assert!(matches!(finished.last(), Some(SearchEvent::Followups { questions }) if questions == &["Why?"]));
+    }
+
+    #[test]
+    fn handles_a_missing_questions_field() {
+        let mut parser = parser();
+        let mut events = parser.push(r#"{"resp": "Only an answer."}"#);
+        events.extend(parser.finish());
+
+        assert_eq!(deltas(&events), "Only an answer.");
+        assert!(matches!(events.last(), Some(SearchEvent::Citations { .. })));
+        assert!(!events.iter().any(|e| matches!(e, SearchEvent::Followups { .. })));
+    }
+}
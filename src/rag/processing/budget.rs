@@ -0,0 +1,149 @@
+use std::env;
+
+use crate::rag::models::{chunks::ResultChunk, DroppedChunk};
+
+/// Prompt tokens available for retrieved context when neither the model nor the environment say otherwise.
+const DEFAULT_CONTEXT_BUDGET: usize = 4_000;
+
+/// Tokens of the context window kept free for the answer and for the error of `estimate_tokens`.
+const ANSWER_TOKEN_RESERVE: usize = 4_096;
+
+/// Rough token estimate of `text`, about four characters per token for the models we run.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Token budget for the context part of a prompt sent to `model`.
+///
+/// `CONTEXT_TOKEN_BUDGET_<MODEL>` (model name uppercased, non-alphanumerics as `_`, e.g.
+/// `CONTEXT_TOKEN_BUDGET_MISTRAL_NEMO`) takes precedence over `CONTEXT_TOKEN_BUDGET`, which
+/// takes precedence over the built-in defaults. The answer prompt runs with `context_window`,
+/// which adds room for the answer on top of the budget.
+pub fn context_budget(model: &str) -> usize {
+    let model_key = model
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect::<String>();
+
+    let configured = env::var(format!("CONTEXT_TOKEN_BUDGET_{}", model_key))
+        .or(env::var("CONTEXT_TOKEN_BUDGET"))
+        .ok()
+        .and_then(|b| b.parse().ok());
+    if let Some(budget) = configured {
+        return budget;
+    }
+
+    match model.split(':').next().unwrap_or(model) {
+        "phi4" => 12_000,
+        "mistral-nemo" => 32_000,
+        _ => DEFAULT_CONTEXT_BUDGET,
+    }
+}
+
+/// Context window (`num_ctx`) to run the answer prompt with, so that a prompt filling the
+/// context budget of `model` still leaves room for the answer.
+///
+/// Ollama loads models with a small default window and silently truncates longer prompts.
+pub fn context_window(model: &str) -> u32 {
+    (context_budget(model) + ANSWER_TOKEN_RESERVE) as u32
+}
+
+/// Splits the chunks into the ones whose prompt blocks fit the budget of `model` and the dropped ones.
+///
+/// Chunks are taken in their ranking order. `reserved` is the rest of the prompt (system
+/// prompt, question), whose tokens are subtracted from the budget first. A chunk that doesn't
+/// fit is dropped, later (shorter) chunks may still be taken.
+pub fn fit_to_budget(chunks: Vec<ResultChunk>, model: &str, reserved: &str) -> (Vec<ResultChunk>, Vec<DroppedChunk>) {
+    let mut remaining = context_budget(model).saturating_sub(estimate_tokens(reserved));
+    let mut kept = vec![];
+    let mut dropped = vec![];
+
+    for chunk in chunks {
+        let tokens = estimate_tokens(&chunk.to_prompt_chunk());
+        if tokens <= remaining {
+            remaining -= tokens;
+            kept.push(chunk);
+        } else {
+            dropped.push(DroppedChunk {
+                id: chunk.id,
+                doc_id: chunk.doc_id,
+                doc_seq_num: chunk.doc_seq_num,
+                estimated_tokens: tokens,
+            });
+        }
+    }
+
+    if !dropped.is_empty() {
+        println!("Dropped {} chunks over the {} context budget of {}", dropped.len(), context_budget(model), model);
+    }
+    (kept, dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &str, content_len: usize) -> ResultChunk {
+        let mut chunk = ResultChunk::for_test(id, "doc", 0, 1.0);
+        chunk.content = "x".repeat(content_len);
+        chunk
+    }
+
+    fn tokens(chunk: &ResultChunk) -> usize {
+        estimate_tokens(&chunk.to_prompt_chunk())
+    }
+
+    // Every test uses its own model name, so the budgets set through the environment don't race.
+    fn set_budget(model: &str, budget: usize) {
+        env::set_var(format!("CONTEXT_TOKEN_BUDGET_{}", model.to_uppercase()), budget.to_string());
+    }
+
+    #[test]
+    fn estimates_four_characters_per_token() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+
+    #[test]
+    fn keeps_chunks_in_order_within_budget() {
+        let chunks = vec![chunk("a", 100), chunk("b", 100), chunk("c", 100)];
+        set_budget("budget_order", tokens(&chunks[0]) * 2);
+
+        let (kept, dropped) = fit_to_budget(chunks, "budget_order", "");
+
+        assert_eq!(kept.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].id, "c");
+    }
+
+    #[test]
+    fn takes_shorter_chunks_after_one_that_does_not_fit() {
+        let chunks = vec![chunk("a", 100), chunk("long", 4_000), chunk("b", 100)];
+        set_budget("budget_skip", tokens(&chunks[0]) * 2);
+
+        let (kept, dropped) = fit_to_budget(chunks, "budget_skip", "");
+
+        assert_eq!(kept.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(dropped[0].id, "long");
+        assert_eq!(dropped[0].estimated_tokens, tokens(&chunk("long", 4_000)));
+    }
+
+    #[test]
+    fn reserved_text_counts_against_the_budget() {
+        let chunks = vec![chunk("a", 100), chunk("b", 100)];
+        let reserved = "y".repeat(40);
+        set_budget("budget_reserved", tokens(&chunks[0]) * 2);
+
+        let (kept, dropped) = fit_to_budget(chunks, "budget_reserved", &reserved);
+
+        assert_eq!(kept.len(), 1);
+        assert_eq!(dropped[0].id, "b");
+    }
+
+    #[test]
+    fn context_window_leaves_room_for_the_answer() {
+        set_budget("budget_window", 1_000);
+        assert_eq!(context_window("budget_window"), (1_000 + ANSWER_TOKEN_RESERVE) as u32);
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeSet;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::rag::models::{chunks::ResultChunk, Citation};
+
+/// Matches citation markers like `[2]` or `[1, 3]`.
+static MARKER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").expect("Invalid citation marker regex"));
+
+/// Citation key of the chunk at `index` in the prompt context.
+pub fn citation_key(index: usize) -> usize {
+    index + 1
+}
+
+/// Citations of all chunks in the prompt context, keyed as they were labelled in the prompt.
+pub fn citation_sources(chunks: &[ResultChunk]) -> Vec<Citation> {
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(index, c)| Citation {
+            marker: citation_key(index),
+            chunk_id: c.id.clone(),
+            doc_id: c.doc_id.clone(),
+            link: c.link(),
+        })
+        .collect()
+}
+
+/// The citations of `sources` whose `[n]` markers appear in the answer, in marker order.
+///
+/// Markers not matching any source (made up by the model) are ignored.
+pub fn citation_map(answer: &str, sources: &[Citation]) -> Vec<Citation> {
+    let markers: BTreeSet<usize> = MARKER
+        .captures_iter(answer)
+        .flat_map(|c| c[1]
+            .split(',')
+            .filter_map(|n| n.trim().parse().ok())
+            .collect::<Vec<usize>>())
+        .collect();
+
+    sources
+        .iter()
+        .filter(|c| markers.contains(&c.marker))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources() -> Vec<Citation> {
+        citation_sources(&[
+            ResultChunk::for_test("p1", "a", 0, 0.9),
+            ResultChunk::for_test("p2", "b", 4, 0.8),
+            ResultChunk::for_test("p3", "c", 1, 0.7),
+        ])
+    }
+
+    fn markers(citations: &[Citation]) -> Vec<usize> {
+        citations.iter().map(|c| c.marker).collect()
+    }
+
+    #[test]
+    fn labels_sources_from_one() {
+        let sources = sources();
+        assert_eq!(markers(&sources), vec![1, 2, 3]);
+        assert_eq!(sources[1].chunk_id, "p2");
+        assert_eq!(sources[1].doc_id, "b");
+    }
+
+    #[test]
+    fn maps_single_and_grouped_markers() {
+        let answer = "Applications close in March [3]. The office helps with housing [1, 3] and visas [2,3].";
+        assert_eq!(markers(&citation_map(answer, &sources())), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn ignores_unknown_markers_and_other_brackets() {
+        let answer = "See [5] and [0]. Lists look like [a] or [1 and 2]. Only [2] is real.";
+        assert_eq!(markers(&citation_map(answer, &sources())), vec![2]);
+    }
+
+    #[test]
+    fn answers_without_markers_cite_nothing() {
+        assert!(citation_map("No citations here.", &sources()).is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+use std::env;
+
+use ollama_rs::generation::parameters::JsonStructure;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::rag::{comm::{structured_qustion::StructuredQuestion, OllamaClient}, models::{history_prompt, Role, Turn}};
+
+/// Previous user messages taken into the fallback retrieval query of a follow-up.
+const RETRIEVAL_HISTORY_MESSAGES: usize = 2;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct StandaloneQuery {
+    /// The latest message rewritten so it can be understood without the conversation.
+    query: String,
+}
+
+/// Rewrites the latest message into a standalone search query using the conversation before it.
+///
+/// Follow-ups like "when is the deadline for that?" don't retrieve anything when embedded
+/// literally, so the model from `CONDENSE_MODEL` (`phi4` when unset) is asked to resolve
+/// their references. Without history the message is used as it is. When the model fails,
+/// the last few user messages are prepended to the message instead.
+pub async fn condense_query(history: &[Turn], message: &str, ollama: &OllamaClient) -> String {
+    if history.is_empty() {
+        return message.to_string();
+    }
+
+    let model = env::var("CONDENSE_MODEL").unwrap_or("phi4".to_string());
+    let system_prompt = "You rewrite chat messages into search queries. Given the conversation \
+        and the latest user message, write a single standalone search query that captures what the \
+        user is asking, resolving references like \"that\" or \"it\" from the conversation. Keep the \
+        language of the latest message. Do not answer the question.";
+    let question = format!("{}\nLatest message:\n{}\n", history_prompt(history), message);
+    let request = StructuredQuestion::from((question, JsonStructure::new::<StandaloneQuery>()))
+        .set_system_prompt(system_prompt)
+        .set_model(&model);
+
+    let condensed = ollama
+        .generate(request)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| serde_json::from_str::<StandaloneQuery>(&r.response).map_err(|e| e.to_string()));
+
+    match condensed {
+        Ok(q) if !q.query.trim().is_empty() => q.query.trim().to_string(),
+        Ok(_) => concatenated_query(history, message),
+        Err(e) => {
+            eprintln!("Failed condensing the query, falling back to the recent messages: {}", e);
+            concatenated_query(history, message)
+        }
+    }
+}
+
+/// The last few user messages followed by the latest one.
+fn concatenated_query(history: &[Turn], message: &str) -> String {
+    let mut parts: Vec<&str> = history
+        .iter()
+        .rev()
+        .filter(|t| t.role == Role::User)
+        .take(RETRIEVAL_HISTORY_MESSAGES)
+        .map(|t| t.content.as_str())
+        .collect();
+    parts.reverse();
+    parts.push(message);
+    parts.join("\n")
+}
@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use crate::rag::{comm::qdrant::{scroll_points, sequence_filter}, models::chunks::ResultChunk};
+
+/// Shortest run of words treated as chunk overlap when stitching, so a single repeated word
+/// at a paragraph boundary isn't dropped.
+const MIN_OVERLAP_WORDS: usize = 5;
+
+/// Widens every hit to a passage spanning its `±window` neighbouring chunks.
+///
+/// Windows of hits from the same document that overlap or touch are merged into one passage,
+/// which is attached to the best ranked of those hits while the others are dropped. The
+/// ranking order of the remaining hits is kept.
+///
+/// # Errors
+/// - Returns an error if fetching the neighbouring chunks from Qdrant fails.
+pub async fn expand_neighbours(chunks: Vec<ResultChunk>, window: i32) -> Result<Vec<ResultChunk>> {
+    if window <= 0 {
+        return Ok(chunks);
+    }
+
+    // Windows per document as (start, end, index of the best ranked hit inside).
+    let mut windows: BTreeMap<String, Vec<(i32, i32, usize)>> = BTreeMap::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        windows
+            .entry(chunk.doc_id.clone())
+            .or_default()
+            .push(((chunk.doc_seq_num - window).max(0), chunk.doc_seq_num + window, index));
+    }
+
+    let mut passages: BTreeMap<usize, (String, (i32, i32))> = BTreeMap::new();
+    for (doc_id, mut doc_windows) in windows {
+        doc_windows.sort();
+        let mut merged: Vec<(i32, i32, usize)> = vec![];
+        for (start, end, index) in doc_windows {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 + 1 => {
+                    last.1 = last.1.max(end);
+                    last.2 = last.2.min(index);
+                }
+                _ => merged.push((start, end, index)),
+            }
+        }
+
+        for (start, end, index) in merged {
+            let (passage, range) = stitch(&doc_id, start, end).await?;
+            passages.insert(index, (passage, range));
+        }
+    }
+
+    Ok(chunks
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, mut chunk)| {
+            let (passage, range) = passages.remove(&index)?;
+            chunk.passage = Some(passage);
+            chunk.passage_range = Some(range);
+            Some(chunk)
+        })
+        .collect())
+}
+
+/// Joins the contents of chunks `start..=end` of the document in reading order.
+///
+/// HyPE stores several points per chunk, so every `doc_seq_num` is only taken once.
+/// The word overlap between consecutive chunks is only kept once, see `join_chunks`.
+async fn stitch(doc_id: &str, start: i32, end: i32) -> Result<(String, (i32, i32))> {
+    let contents: BTreeMap<i32, String> = scroll_points(Some(sequence_filter(doc_id, start, end)))
+        .await?
+        .into_iter()
+        .map(|p| {
+            let chunk: ResultChunk = p.into();
+            (chunk.doc_seq_num, chunk.content)
+        })
+        .collect();
+
+    let range = match (contents.keys().next(), contents.keys().next_back()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => (start, end),
+    };
+    let passage = join_chunks(contents.into_values().collect());
+    Ok((passage, range))
+}
+
+/// Joins consecutive chunks with newlines, dropping the words each chunk repeats from the end
+/// of the previous one.
+fn join_chunks(contents: Vec<String>) -> String {
+    let mut passage: Vec<String> = vec![];
+    for content in contents {
+        let content = match passage.last() {
+            Some(previous) => trim_overlap(previous, &content),
+            None => content,
+        };
+        if !content.is_empty() {
+            passage.push(content);
+        }
+    }
+    passage.join("\n")
+}
+
+/// Removes the longest leading run of words of `next` that `previous` ends with.
+fn trim_overlap(previous: &str, next: &str) -> String {
+    let previous: Vec<&str> = previous.split_whitespace().collect();
+    let next: Vec<&str> = next.split_whitespace().collect();
+
+    let overlap = (MIN_OVERLAP_WORDS..=previous.len().min(next.len()))
+        .rev()
+        .find(|n| previous[previous.len() - n..] == next[..*n])
+        .unwrap_or(0);
+    next[overlap..].join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(range: std::ops::Range<usize>) -> String {
+        range.map(|i| format!("w{}", i)).collect::<Vec<String>>().join(" ")
+    }
+
+    #[test]
+    fn drops_the_overlap_of_following_chunks() {
+        let passage = join_chunks(vec![words(0..250), words(220..470), words(440..500)]);
+        assert_eq!(passage, format!("{}\n{}\n{}", words(0..250), words(250..470), words(470..500)));
+    }
+
+    #[test]
+    fn keeps_chunks_without_overlap() {
+        let passage = join_chunks(vec!["end of a paragraph".into(), "paragraph starts here".into()]);
+        assert_eq!(passage, "end of a paragraph\nparagraph starts here");
+    }
+
+    #[test]
+    fn skips_chunks_covered_by_the_previous_one() {
+        let passage = join_chunks(vec![words(0..300), words(220..300), words(300..320)]);
+        assert_eq!(passage, format!("{}\n{}", words(0..300), words(300..320)));
+    }
+}
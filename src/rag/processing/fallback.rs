@@ -0,0 +1,93 @@
+use tokio_stream::iter;
+
+use crate::rag::models::{chunks::ResultChunk, SearchEvent, SearchEventStream};
+
+/// Document titles suggested in the fallback answer.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Whether the best dense score of the retrieved chunks reaches `min_relevance`.
+pub fn is_confident(chunks: &[ResultChunk], min_relevance: f32) -> bool {
+    best_score(chunks).is_some_and(|s| s >= min_relevance)
+}
+
+pub fn best_score(chunks: &[ResultChunk]) -> Option<f32> {
+    chunks.iter().map(|c| c.score).reduce(f32::max)
+}
+
+/// Titles of the documents closest to the query, for pointing the student somewhere.
+pub fn suggestions(chunks: &[ResultChunk]) -> Vec<String> {
+    let mut titles: Vec<String> = vec![];
+    for chunk in chunks {
+        if !chunk.original_name.is_empty() && !titles.contains(&chunk.original_name) {
+            titles.push(chunk.original_name.clone());
+        }
+        if titles.len() == MAX_SUGGESTIONS {
+            break;
+        }
+    }
+    titles
+}
+
+/// Canned answer sent instead of a generated one, in Slovenian or English following the query.
+pub fn fallback_events(query: &str, suggestions: &[String]) -> SearchEventStream {
+    let slovenian = is_slovenian(query);
+    let mut text = if slovenian {
+        "Tega v dokumentih univerze žal nisem našla.".to_string()
+    } else {
+        "Sorry, I couldn't find this in the university documents.".to_string()
+    };
+
+    if !suggestions.is_empty() {
+        text.push_str(if slovenian {
+            " Morda vam pomagajo ti dokumenti:\n"
+        } else {
+            " These documents might help:\n"
+        });
+        for title in suggestions {
+            text.push_str(&format!("- {}\n", title));
+        }
+    }
+
+    Box::pin(iter([
+        SearchEvent::Delta { text },
+        SearchEvent::Done { prompt_tokens: None, answer_tokens: None, generation_ms: None },
+    ]))
+}
+
+/// Rough guess whether the query is written in Slovenian, the other language students use being English.
+///
+/// Either a Slovenian letter (č, š, ž) or at least two common Slovenian words are needed.
+/// Short words that are also English ("in", "so", "na", ...) aren't counted.
+fn is_slovenian(query: &str) -> bool {
+    const SLOVENIAN_WORDS: [&str; 20] = [
+        "kje", "kdaj", "kako", "kaj", "zakaj", "kdo", "koliko", "kateri", "katera", "katero",
+        "je", "za", "ki", "lahko", "sem", "smo", "ste", "tudi", "ter", "pri",
+    ];
+
+    let query = query.to_lowercase();
+    query.contains(['č', 'š', 'ž'])
+        || query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| SLOVENIAN_WORDS.contains(w))
+            .count() >= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_queries_with_shared_short_words_are_english() {
+        assert!(!is_slovenian("What courses are offered in the summer semester?"));
+        assert!(!is_slovenian("Why is it so hard to get into the dorms in Koper?"));
+        assert!(!is_slovenian("Can I enrol in a course so late, is there a deadline?"));
+        assert!(!is_slovenian("Who is Ali and what is the na score?"));
+    }
+
+    #[test]
+    fn recognizes_slovenian_queries() {
+        assert!(is_slovenian("Kdaj je rok za prijavo?"));
+        assert!(is_slovenian("Kje lahko dobim potrdilo o vpisu"));
+        assert!(is_slovenian("vpis na študij"));
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::rag::models::chunks::ResultChunk;
+
+/// Rank smoothing constant of reciprocal rank fusion, 60 as in the original paper.
+const RRF_K: f32 = 60.0;
+
+/// Merges several rankings of the same collection with weighted reciprocal rank fusion.
+///
+/// Every ranking comes with its weight, a chunk at (zero based) rank `r` of a ranking
+/// contributes `weight / (RRF_K + r + 1)`. Chunks are identified by `(doc_id, doc_seq_num)`,
+/// as HyPE stores one point per generated question, and only count once per ranking at
+/// their best rank. Chunks found by more than one ranking are merged, keeping the best
+/// dense and keyword scores. The result is sorted by the fused score, which is recorded
+/// in `fusion_score`.
+pub fn reciprocal_rank_fusion(rankings: Vec<(Vec<ResultChunk>, f32)>) -> Vec<ResultChunk> {
+    let mut fused: HashMap<(String, i32), (f32, ResultChunk)> = HashMap::new();
+
+    for (ranking, weight) in rankings {
+        if weight <= 0.0 {
+            continue;
+        }
+        let mut seen: HashSet<(String, i32)> = HashSet::new();
+        let ranking = ranking
+            .into_iter()
+            .filter(|chunk| seen.insert((chunk.doc_id.clone(), chunk.doc_seq_num)));
+        for (rank, chunk) in ranking.enumerate() {
+            let contribution = weight / (RRF_K + rank as f32 + 1.0);
+            let key = (chunk.doc_id.clone(), chunk.doc_seq_num);
+            match fused.get_mut(&key) {
+                Some((score, existing)) => {
+                    *score += contribution;
+                    existing.score = existing.score.max(chunk.score);
+                    existing.keyword_score = match (existing.keyword_score, chunk.keyword_score) {
+                        (Some(a), Some(b)) => Some(a.max(b)),
+                        (a, b) => a.or(b),
+                    };
+                }
+                None => {
+                    fused.insert(key, (contribution, chunk));
+                }
+            }
+        }
+    }
+
+    let mut result: Vec<ResultChunk> = fused
+        .into_values()
+        .map(|(score, mut chunk)| {
+            chunk.fusion_score = Some(score);
+            chunk
+        })
+        .collect();
+    result.sort_by(|a, b| b.fusion_score.partial_cmp(&a.fusion_score).unwrap_or(std::cmp::Ordering::Equal));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(chunks: &[ResultChunk]) -> Vec<(&str, i32)> {
+        chunks.iter().map(|c| (c.doc_id.as_str(), c.doc_seq_num)).collect()
+    }
+
+    #[test]
+    fn chunks_found_by_both_rankings_rank_first() {
+        let dense = vec![
+            ResultChunk::for_test("p1", "a", 0, 0.9),
+            ResultChunk::for_test("p2", "b", 0, 0.8),
+        ];
+        let mut keyword = ResultChunk::for_test("p3", "b", 0, 0.0);
+        keyword.keyword_score = Some(7.5);
+
+        let fused = reciprocal_rank_fusion(vec![(dense, 1.0), (vec![keyword], 1.0)]);
+        assert_eq!(keys(&fused), vec![("b", 0), ("a", 0)]);
+        assert_eq!(fused[0].score, 0.8);
+        assert_eq!(fused[0].keyword_score, Some(7.5));
+        let expected = 1.0 / (RRF_K + 2.0) + 1.0 / (RRF_K + 1.0);
+        assert!((fused[0].fusion_score.unwrap() - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn hype_points_of_a_chunk_count_once_per_ranking() {
+        let dense = vec![
+            ResultChunk::for_test("q0", "a", 0, 0.9),
+            ResultChunk::for_test("q1", "a", 0, 0.85),
+            ResultChunk::for_test("q2", "a", 0, 0.8),
+            ResultChunk::for_test("p", "b", 3, 0.7),
+        ];
+        let other = vec![ResultChunk::for_test("p", "b", 3, 0.7)];
+
+        let fused = reciprocal_rank_fusion(vec![(dense, 1.0), (other, 1.0)]);
+        assert_eq!(keys(&fused), vec![("b", 3), ("a", 0)]);
+        assert!((fused[1].fusion_score.unwrap() - 1.0 / (RRF_K + 1.0)).abs() < 1e-6);
+        assert!((fused[0].fusion_score.unwrap() - (1.0 / (RRF_K + 2.0) + 1.0 / (RRF_K + 1.0))).abs() < 1e-6);
+    }
+
+    #[test]
+    fn weights_scale_and_disable_rankings() {
+        let dense = vec![ResultChunk::for_test("p1", "a", 0, 0.9)];
+        let keyword = vec![ResultChunk::for_test("p2", "b", 0, 0.0)];
+
+        let fused = reciprocal_rank_fusion(vec![(dense.clone(), 1.0), (keyword.clone(), 2.0)]);
+        assert_eq!(keys(&fused), vec![("b", 0), ("a", 0)]);
+
+        let fused = reciprocal_rank_fusion(vec![(dense, 1.0), (keyword, 0.0)]);
+        assert_eq!(keys(&fused), vec![("a", 0)]);
+    }
+}
@@ -0,0 +1,139 @@
+use std::env;
+
+use ollama_rs::generation::parameters::JsonStructure;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+use crate::rag::{comm::{structured_qustion::StructuredQuestion, OllamaClient}, models::{chunks::ResultChunk, Grounding, SearchEvent, SearchEventStream}};
+
+/// Sent in place of an answer that scored below the grounding threshold.
+const SUPPRESSED_ANSWER: &str = "I couldn't verify an answer to this question against the documents. \
+    Please check the sources or rephrase the question.";
+
+/// Error sent instead of an answer that has to be verified when the judge fails.
+const UNVERIFIED_ANSWER: &str = "The answer could not be verified against the documents.";
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct Judgement {
+    /// Every factual claim of the answer.
+    claims: Vec<JudgedClaim>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct JudgedClaim {
+    claim: String,
+    /// Whether the context states the claim.
+    supported: bool,
+}
+
+/// Checks the generated answer against the context chunks before the stream completes.
+///
+/// A `grounding` event is sent before `done`. Without a `threshold` the answer is streamed
+/// as usual. With one, the answer deltas, citations and follow-up questions are held back
+/// until the answer is verified and replaced by a short notice when its score is below the
+/// threshold. If the judge fails, the answer is withheld and the stream ends with an error.
+pub fn grounded_events(mut events: SearchEventStream, chunks: &[ResultChunk], threshold: Option<f32>) -> SearchEventStream {
+    let context: Vec<String> = chunks.iter().map(|c| c.to_prompt_chunk()).collect();
+    let (tx, rx) = mpsc::channel::<SearchEvent>(1000);
+
+    tokio::spawn(async move {
+        let ollama = OllamaClient::default();
+        let mut answer = String::new();
+        let mut held_back: Vec<SearchEvent> = vec![];
+
+        while let Some(event) = events.next().await {
+            match event {
+                SearchEvent::Delta { ref text } => {
+                    answer.push_str(text);
+                    if threshold.is_some() {
+                        held_back.push(event);
+                        continue;
+                    }
+                }
+                SearchEvent::Citations { .. } | SearchEvent::Followups { .. } if threshold.is_some() => {
+                    held_back.push(event);
+                    continue;
+                }
+                SearchEvent::Done { .. } => {
+                    let grounding = verify_grounding(&answer, &context, threshold, &ollama).await;
+                    if threshold.is_some() && grounding.is_none() {
+                        let _ = tx.send(SearchEvent::Error { message: UNVERIFIED_ANSWER.to_string() }).await;
+                        return;
+                    }
+                    let suppressed = grounding.as_ref().is_some_and(|g| g.suppressed);
+                    if suppressed {
+                        let _ = tx.send(SearchEvent::Delta { text: SUPPRESSED_ANSWER.to_string() }).await;
+                    } else {
+                        for held in held_back.drain(..) {
+                            let _ = tx.send(held).await;
+                        }
+                    }
+                    if let Some(grounding) = grounding {
+                        let _ = tx.send(SearchEvent::Grounding { grounding }).await;
+                    }
+                }
+                SearchEvent::Error { .. } => {
+                    let _ = tx.send(event).await;
+                    return;
+                }
+                _ => {}
+            }
+            if tx.send(event).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    Box::pin(ReceiverStream::new(rx))
+}
+
+/// Asks the judge from `GROUNDING_MODEL` (`phi4` when unset) which claims of the answer the context supports.
+///
+/// The score is the share of supported claims, an answer without claims counts as grounded.
+/// Returns `None` when the judge fails, the answer is then passed through unverified unless
+/// a threshold was set.
+async fn verify_grounding(answer: &str, context: &[String], threshold: Option<f32>, ollama: &OllamaClient) -> Option<Grounding> {
+    let model = env::var("GROUNDING_MODEL").unwrap_or("phi4".to_string());
+    let system_prompt = "You are a fact checker. Split the answer into its individual factual claims \
+        (dates, deadlines, names, numbers, requirements, procedures) and decide for every claim whether \
+        the context explicitly supports it. Greetings and suggestions to contact someone are not claims. \
+        Use only the context, not your own knowledge.";
+    let question = format!("Context:\n{}\n\nAnswer:\n{}\n", context.join("\n"), answer);
+    let request = StructuredQuestion::from((question, JsonStructure::new::<Judgement>()))
+        .set_system_prompt(system_prompt)
+        .set_model(&model);
+
+    let judgement = ollama
+        .generate(request)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| serde_json::from_str::<Judgement>(&r.response).map_err(|e| e.to_string()));
+    let judgement = match judgement {
+        Ok(j) => j,
+        Err(e) => {
+            eprintln!("Failed verifying the grounding of the answer: {}", e);
+            return None;
+        }
+    };
+
+    let supported = judgement.claims.iter().filter(|c| c.supported).count();
+    let score = if judgement.claims.is_empty() {
+        1.0
+    } else {
+        supported as f32 / judgement.claims.len() as f32
+    };
+    let unsupported_claims = judgement
+        .claims
+        .into_iter()
+        .filter(|c| !c.supported)
+        .map(|c| c.claim)
+        .collect();
+
+    Some(Grounding {
+        score,
+        unsupported_claims,
+        suppressed: threshold.is_some_and(|t| score < t),
+    })
+}
@@ -0,0 +1,24 @@
+use crate::rag::comm::{question::Question, OllamaClient};
+
+/// Drafts a passage that could answer the query, to be embedded in place of (or next to) the query.
+///
+/// Chunks are stored next to the questions they answer (HyPE), this is the opposite direction:
+/// a made-up answer tends to land close to chunks written as answers. Returns `None` when the
+/// generation fails or comes back empty.
+pub async fn hypothetical_answer(query: &str, ollama: &OllamaClient) -> Option<String> {
+    let system_prompt = "You write short passages for the documents of University of Primorska. \
+        Given a question, write a single paragraph as it could appear in an official university \
+        document answering it. Make up plausible details if you don't know them. Keep the \
+        language of the question.";
+
+    match ollama
+        .generate(Question::from(query).set_system_prompt(system_prompt))
+        .await {
+            Ok(r) if !r.response.trim().is_empty() => Some(r.response.trim().to_string()),
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("Failed generating a hypothetical answer, searching with the query: {}", e);
+                None
+            }
+        }
+}
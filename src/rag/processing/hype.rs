@@ -18,6 +18,8 @@ fn replace_chunks(file: ChunkedFile<Chunk>, hype_chunks: Vec<HypeChunk>) -> Chun
         file_type,
         chunks: _,
         internal_id,
+        original_name,
+        content_hash,
         tags,
         original_file_description,
         syntetic_file_description,
@@ -27,6 +29,8 @@ fn replace_chunks(file: ChunkedFile<Chunk>, hype_chunks: Vec<HypeChunk>) -> Chun
         file_type,
         chunks: hype_chunks,
         internal_id,
+        original_name,
+        content_hash,
         tags,
         original_file_description,
         syntetic_file_description,
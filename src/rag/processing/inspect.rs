@@ -0,0 +1,69 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::Value;
+
+use crate::rag::models::{chunks::ResultChunk, DocumentChunk, DocumentOverview};
+
+/// Groups points by `doc_id` into one overview per document, ordered by `doc_id`.
+pub fn group_documents(points: Vec<ResultChunk>) -> Vec<DocumentOverview> {
+    let mut documents: BTreeMap<String, (DocumentOverview, BTreeSet<i32>)> = BTreeMap::new();
+
+    for point in points {
+        let (document, seq_nums) = documents
+            .entry(point.doc_id.clone())
+            .or_insert_with(|| (DocumentOverview {
+                doc_id: point.doc_id.clone(),
+                doc_summary: point.doc_summary.clone(),
+                tags: vec![],
+                chunk_count: 0,
+                question_count: 0,
+            }, BTreeSet::new()));
+
+        seq_nums.insert(point.doc_seq_num);
+        if point_question(&point).is_some() {
+            document.question_count += 1;
+        }
+        for tag in point.tags {
+            if !document.tags.contains(&tag) {
+                document.tags.push(tag);
+            }
+        }
+    }
+
+    documents
+        .into_values()
+        .map(|(mut document, seq_nums)| {
+            document.chunk_count = seq_nums.len();
+            document
+        })
+        .collect()
+}
+
+/// Collapses the points of a single document into its chunks, ordered by `doc_seq_num`.
+pub fn group_chunks(points: Vec<ResultChunk>) -> Vec<DocumentChunk> {
+    let mut chunks: BTreeMap<i32, DocumentChunk> = BTreeMap::new();
+
+    for point in points {
+        let question = point_question(&point);
+        let chunk = chunks
+            .entry(point.doc_seq_num)
+            .or_insert_with(|| DocumentChunk {
+                doc_seq_num: point.doc_seq_num,
+                content: point.content,
+                questions: vec![],
+            });
+        if let Some(question) = question {
+            chunk.questions.push(question);
+        }
+    }
+
+    chunks.into_values().collect()
+}
+
+/// HyPE points store the generated question as the first entry of `additional_data`.
+fn point_question(point: &ResultChunk) -> Option<String> {
+    match &point.additional_data {
+        Value::Array(values) => values.first().and_then(|v| v.as_str()).map(|v| v.to_string()),
+        _ => None,
+    }
+}
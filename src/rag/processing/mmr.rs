@@ -0,0 +1,103 @@
+use crate::rag::models::chunks::ResultChunk;
+
+/// Picks `k` chunks with maximal marginal relevance.
+///
+/// Every step takes the chunk maximizing `lambda * relevance - (1 - lambda) * max similarity`
+/// to the already picked chunks, so near-identical chunks (overlapping windows, duplicated
+/// documents) don't crowd out the rest. Relevance is the fused score when available and the
+/// vector score otherwise, scaled to `0..=1`. Similarity is measured on the stored point
+/// vectors in `vector`, so candidates have to be one per chunk (see `dedup`) and a HyPE chunk
+/// is represented by its best ranked question. Chunks without a vector count as dissimilar
+/// to everything.
+pub fn mmr(mut candidates: Vec<ResultChunk>, lambda: f32, k: usize) -> Vec<ResultChunk> {
+    let relevance: Vec<f32> = candidates
+        .iter()
+        .map(|c| c.fusion_score.unwrap_or(c.score))
+        .collect();
+    let max_relevance = relevance.iter().cloned().fold(f32::MIN, f32::max);
+    let mut relevance: Vec<f32> = relevance
+        .into_iter()
+        .map(|r| if max_relevance > 0.0 { r / max_relevance } else { r })
+        .collect();
+
+    let mut selected: Vec<ResultChunk> = vec![];
+    while selected.len() < k && !candidates.is_empty() {
+        let mut best = 0;
+        let mut best_score = f32::MIN;
+        for (index, candidate) in candidates.iter().enumerate() {
+            let redundancy = selected
+                .iter()
+                .map(|s| similarity(candidate, s))
+                .fold(0.0, f32::max);
+            let score = lambda * relevance[index] - (1.0 - lambda) * redundancy;
+            if score > best_score {
+                best = index;
+                best_score = score;
+            }
+        }
+        relevance.remove(best);
+        selected.push(candidates.remove(best));
+    }
+
+    selected
+}
+
+fn similarity(a: &ResultChunk, b: &ResultChunk) -> f32 {
+    let (Some(a), Some(b)) = (&a.vector, &b.vector) else {
+        return 0.0;
+    };
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &str, score: f32, vector: Vec<f32>) -> ResultChunk {
+        let mut chunk = ResultChunk::for_test(id, id, 0, score);
+        chunk.vector = Some(vector);
+        chunk
+    }
+
+    fn ids(chunks: &[ResultChunk]) -> Vec<&str> {
+        chunks.iter().map(|c| c.id.as_str()).collect()
+    }
+
+    fn candidates() -> Vec<ResultChunk> {
+        vec![
+            chunk("a", 0.9, vec![1.0, 0.0]),
+            chunk("a_copy", 0.89, vec![1.0, 0.01]),
+            chunk("b", 0.7, vec![0.0, 1.0]),
+        ]
+    }
+
+    #[test]
+    fn lambda_one_keeps_the_relevance_order() {
+        assert_eq!(ids(&mmr(candidates(), 1.0, 2)), ["a", "a_copy"]);
+    }
+
+    #[test]
+    fn skips_near_duplicates_for_diversity() {
+        assert_eq!(ids(&mmr(candidates(), 0.5, 2)), ["a", "b"]);
+    }
+
+    #[test]
+    fn chunks_without_vectors_count_as_dissimilar() {
+        let mut candidates = candidates();
+        candidates[1].vector = None;
+        assert_eq!(ids(&mmr(candidates, 0.5, 2)), ["a", "a_copy"]);
+    }
+
+    #[test]
+    fn returns_at_most_k_chunks() {
+        assert_eq!(mmr(candidates(), 0.5, 5).len(), 3);
+        assert!(mmr(vec![], 0.5, 5).is_empty());
+    }
+}
@@ -6,15 +6,38 @@ mod prepare;
 mod dedup_embeddings;
 mod prompt;
 mod hype;
+mod hyde;
 mod embedd_file;
 mod summarize;
 mod chunking;
 mod recursive_prompt;
+mod inspect;
+mod answer_stream;
+mod budget;
+mod citations;
+mod condense;
+mod fallback;
+mod fusion;
+mod grounding;
+mod expand;
+mod mmr;
+mod multi_query;
+mod rerank;
 
+pub use condense::condense_query;
 pub use dedup_embeddings::dedup;
+pub use expand::expand_neighbours;
+pub use fallback::{best_score, fallback_events, is_confident, suggestions};
+pub use fusion::reciprocal_rank_fusion;
+pub use grounding::grounded_events;
 pub use hype::hype;
+pub use hyde::hypothetical_answer;
+pub use mmr::mmr;
+pub use multi_query::expand_query;
+pub use inspect::{group_chunks, group_documents};
 pub use prompt::prompt;
 pub use recursive_prompt::recursive_prompt;
+pub use rerank::rerank;
 pub use prepare::prepare_for_upload;
 
 type ChunkSize = i32;
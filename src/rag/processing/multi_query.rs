@@ -0,0 +1,52 @@
+use std::env;
+
+use ollama_rs::generation::parameters::JsonStructure;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::rag::comm::{structured_qustion::StructuredQuestion, OllamaClient};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct QueryVariants {
+    /// Paraphrases of the query, or sub-questions when it asks about several topics.
+    queries: Vec<String>,
+}
+
+/// Asks the model from `EXPANSION_MODEL` (`phi4` when unset) for up to `count` alternative search queries.
+///
+/// Compound questions are split into sub-questions, simple ones are paraphrased so chunks
+/// worded differently are found too. Returns no variants when the model fails, the search
+/// then just runs with the original query.
+pub async fn expand_query(query: &str, count: usize, ollama: &OllamaClient) -> Vec<String> {
+    let model = env::var("EXPANSION_MODEL").unwrap_or("phi4".to_string());
+    let system_prompt = format!(
+        "You help a search engine find documents about University of Primorska. Given a search \
+        query, write up to {} alternative search queries. If the query asks about several topics, \
+        write one focused query per topic, otherwise paraphrase it using different wording. Keep \
+        the language of the query. Do not answer it.",
+        count
+    );
+    let request = StructuredQuestion::from((format!("Query:\n{}\n", query), JsonStructure::new::<QueryVariants>()))
+        .set_system_prompt(&system_prompt)
+        .set_model(&model);
+
+    let variants = ollama
+        .generate(request)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| serde_json::from_str::<QueryVariants>(&r.response).map_err(|e| e.to_string()));
+
+    match variants {
+        Ok(v) => v
+            .queries
+            .into_iter()
+            .map(|q| q.trim().to_string())
+            .filter(|q| !q.is_empty() && q != query)
+            .take(count)
+            .collect(),
+        Err(e) => {
+            eprintln!("Failed expanding the query, searching with the original only: {}", e);
+            vec![]
+        }
+    }
+}
@@ -1,23 +1,28 @@
 use crate::rag::{
     comm::{embedding::Embeddable, OllamaClient}, 
-    models::{chunks::EmbeddedChunk, ChunkedFile}
+    models::{chunks::{DocumentMetadata, EmbeddedChunk}, ChunkedFile}
 };
 use anyhow::Result;
+use chrono::Utc;
 
 use super::embedd_file::embedd_file;
 
 
 pub async fn prepare_for_upload<T>(file: ChunkedFile<T>, ollama: &OllamaClient) -> Result<Vec<EmbeddedChunk>> where T: Embeddable {
-    let descr = file.syntetic_file_description.clone();
-    let tags: Vec<String> = match &file.tags {
-        Some(t) => t.clone(),
-        None => vec![],
+    let document = DocumentMetadata {
+        doc_id: file.internal_id.clone(),
+        doc_summary: file.syntetic_file_description.clone().unwrap_or_default(),
+        content_hash: file.content_hash.clone(),
+        tags: file.tags.clone().unwrap_or_default(),
+        file_type: file.file_type.clone(),
+        original_name: file.original_name.clone(),
+        ingested_at: Utc::now(),
     };
     let embedded_file = embedd_file(file, ollama).await?;
     Ok(embedded_file
         .chunks
         .into_iter()
-        .filter_map(|c| c.prepare_for_upload(embedded_file.internal_id.to_string(), descr.clone(), tags.clone()).ok())
+        .filter_map(|c| c.prepare_for_upload(&document).ok())
         .flatten()
         .collect())
 }
\ No newline at end of file
@@ -1,14 +1,25 @@
+use super::{answer_stream::answer_events, budget::fit_to_budget, citations::{citation_key, citation_sources}};
 use crate::rag::{comm::{question::Question, OllamaClient}, models::{chunks::ResultChunk, SearchResult}};
 use ollama_rs::{error::OllamaError, generation::completion::GenerationResponseStream};
 
 
 
 pub async fn prompt(prompt: String, chunks: Vec<ResultChunk>, ollama: &OllamaClient) -> Result<SearchResult, OllamaError> {
-    let llm_prompt = construct_prompt(prompt, &chunks);
+    let (chunks, dropped) = fit_to_budget(chunks, "mistral-nemo", &prompt);
+    let llm_prompt = construct_prompt(prompt.clone(), &chunks);
     let stream: GenerationResponseStream = ollama.generate_stream(llm_prompt).await?;
+    let sources = citation_sources(&chunks);
     Ok(SearchResult {
+        query: prompt,
+        expanded_queries: vec![],
+        hypothetical_answer: None,
+        fallback: false,
+        suggestions: vec![],
+        best_score: None,
+        query_embedding: vec![],
         chunks,
-        stream,
+        dropped,
+        stream: answer_events(stream, false, sources),
     })
 } 
 
@@ -18,12 +29,14 @@ fn construct_prompt(prompt: String, chunks: &Vec<ResultChunk>) -> Question {
         about University of Primorska. Your name is Urška. Given a \
         question, help navigate through the files and the information. You are allowed to read \
         some of the documents. Please answer in markdown format. When applicable add links. The uni \
-        website is at https://www.famnit.upr.si ".to_string();
+        website is at https://www.famnit.upr.si Every chunk is labelled with a number like [1]. \
+        After each statement you take from a chunk, cite it with its label, e.g. [1] or [2, 3]. ".to_string();
 
 
     let context: Vec<String> = chunks
         .iter()
-        .map(|c| c.to_prompt_chunk())
+        .enumerate()
+        .map(|(i, c)| format!("[{}] {}", citation_key(i), c.to_prompt_chunk()))
         .collect();
 
     let question = format!(
@@ -1,46 +1,69 @@
-use crate::rag::{comm::{question::Question, structured_qustion::StructuredQuestion, OllamaClient}, models::{chunks::ResultChunk, SearchResult}};
-use ollama_rs::{error::OllamaError, generation::{completion::GenerationResponseStream, parameters::JsonStructure}};
+use super::{answer_stream::answer_events, budget::{context_window, fit_to_budget}, citations::{citation_key, citation_sources}};
+use crate::rag::{comm::{question::Question, structured_qustion::StructuredQuestion, OllamaClient}, models::{chunks::ResultChunk, history_prompt, SearchResult, Turn}};
+use ollama_rs::{error::OllamaError, generation::{completion::{request::GenerationRequest, GenerationResponseStream}, options::GenerationOptions, parameters::JsonStructure}};
 use schemars::{schema_for, JsonSchema};
+use serde::Deserialize;
 
 
 
-pub async fn recursive_prompt(prompt: String, chunks: Vec<ResultChunk>, ollama: &OllamaClient) -> Result<SearchResult, OllamaError> {
-    let llm_prompt = construct_prompt(prompt, &chunks);
+/// Model answering the question, the context is budgeted against its window.
+const ANSWER_MODEL: &str = "phi4";
+
+const SYSTEM_MESSAGE: &str = "You are an assistant who is helping students find information \
+    about University of Primorska. Your name is Urška. Given a \
+    question, help navigate through the files and the information. You are allowed to read \
+    some of the documents. Please answer in markdown format. When applicable add links. The uni \
+    website is at https://www.famnit.upr.si Every chunk is labelled with a number like [1]. \
+    After each statement you take from a chunk, cite it with its label, e.g. [1] or [2, 3]. ";
+
+
+pub async fn recursive_prompt(prompt: String, history: &[Turn], chunks: Vec<ResultChunk>, ollama: &OllamaClient) -> Result<SearchResult, OllamaError> {
+    let history = history_prompt(history);
+    let (chunks, dropped) = fit_to_budget(chunks, ANSWER_MODEL, &format!("{}\n{}\n{}", SYSTEM_MESSAGE, history, prompt));
+    let llm_prompt: GenerationRequest = construct_prompt(prompt.clone(), &history, &chunks).into();
+    // Run with a window the budgeted prompt and the answer fit in, Ollama's default is far smaller.
+    let llm_prompt = llm_prompt.options(GenerationOptions::default().num_ctx(context_window(ANSWER_MODEL)));
     let stream: GenerationResponseStream = ollama.generate_stream(llm_prompt).await?;
+    let sources = citation_sources(&chunks);
     Ok(SearchResult {
+        query: prompt,
+        expanded_queries: vec![],
+        hypothetical_answer: None,
+        fallback: false,
+        suggestions: vec![],
+        best_score: None,
+        query_embedding: vec![],
         chunks,
-        stream,
+        dropped,
+        stream: answer_events(stream, true, sources),
     })
 } 
 
-#[derive(Debug, JsonSchema)]
+#[derive(Debug, JsonSchema, Deserialize)]
 pub struct TestFormat {
-    resp: String,
-    questions: Vec<String>,
+    pub resp: String,
+    pub questions: Vec<String>,
 }
 
 
-fn construct_prompt(prompt: String, chunks: &Vec<ResultChunk>) -> StructuredQuestion {
-    let system_message = "You are an assistant who is helping students find information \
-        about University of Primorska. Your name is Urška. Given a \
-        question, help navigate through the files and the information. You are allowed to read \
-        some of the documents. Please answer in markdown format. When applicable add links. The uni \
-        website is at https://www.famnit.upr.si ".to_string();
-
-
+fn construct_prompt(prompt: String, history: &str, chunks: &Vec<ResultChunk>) -> StructuredQuestion {
     let context: Vec<String> = chunks
         .iter()
-        .map(|c| c.to_prompt_chunk())
+        .enumerate()
+        .map(|(i, c)| format!("[{}] {}", citation_key(i), c.to_prompt_chunk()))
         .collect();
 
     let question = format!(
-        "{}\nQuestion:\n{}\n", 
+        "{}\n{}Question:\n{}\n", 
         context.join("\n"),
+        history,
         prompt
     );
 
 
     println!("{question}");
 
-    StructuredQuestion::from((question, JsonStructure::new::<TestFormat>())).set_system_prompt(&system_message)
+    StructuredQuestion::from((question, JsonStructure::new::<TestFormat>()))
+        .set_system_prompt(SYSTEM_MESSAGE)
+        .set_model(ANSWER_MODEL)
 }
\ No newline at end of file
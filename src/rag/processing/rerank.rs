@@ -0,0 +1,56 @@
+use std::env;
+
+use ollama_rs::generation::parameters::JsonStructure;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::rag::{comm::{structured_qustion::StructuredQuestion, OllamaClient}, models::chunks::ResultChunk};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct RelevanceScore {
+    /// How well the passage answers the query, from 0 (unrelated) to 10 (answers it fully).
+    score: f32,
+}
+
+/// Scores every (query, chunk content) pair with the reranker model and keeps the best `top_n` chunks.
+///
+/// The reranker model is taken from `RERANK_MODEL` (`phi4` when unset) and is asked for a
+/// structured relevance score, which is normalized to `0..=1` and stored in `rerank_score`.
+/// Chunks the model fails to score get `0` and sink to the bottom, the vector order is kept
+/// between chunks with equal scores.
+pub async fn rerank(query: &str, mut chunks: Vec<ResultChunk>, top_n: usize, ollama: &OllamaClient) -> Vec<ResultChunk> {
+    let questions = rerank_prompts(query, &chunks);
+    let answers = ollama.answer_all(questions).await;
+
+    for (chunk, answer) in chunks.iter_mut().zip(answers) {
+        let score = match serde_json::from_str::<RelevanceScore>(&answer) {
+            Ok(r) => r.score.clamp(0.0, 10.0) / 10.0,
+            Err(e) => {
+                eprintln!("Reranker returned an unusable score for chunk {}: {}", chunk.id, e);
+                0.0
+            }
+        };
+        chunk.rerank_score = Some(score);
+    }
+
+    chunks.sort_by(|a, b| b.rerank_score.partial_cmp(&a.rerank_score).unwrap_or(std::cmp::Ordering::Equal));
+    chunks.truncate(top_n);
+    chunks
+}
+
+fn rerank_prompts(query: &str, chunks: &[ResultChunk]) -> Vec<StructuredQuestion> {
+    let model = env::var("RERANK_MODEL").unwrap_or("phi4".to_string());
+    let system_prompt = "You are a search relevance judge. Given a query and a passage, rate \
+        how well the passage answers the query on a scale from 0 (unrelated) to 10 (answers it fully). \
+        Judge only the passage, not what you know yourself.";
+
+    chunks
+        .iter()
+        .map(|c| {
+            let question = format!("Query:\n{}\n\nPassage:\n{}\n", query, c.content);
+            StructuredQuestion::from((question, JsonStructure::new::<RelevanceScore>()))
+                .set_system_prompt(system_prompt)
+                .set_model(&model)
+        })
+        .collect()
+}
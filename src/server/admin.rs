@@ -0,0 +1,156 @@
+use actix_web::{get, web::{self, Query}, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::{HashMap, HashSet}, env};
+
+use super::search_log::{read_entries, LogEntry, Rating, SearchRecord};
+
+/// Best score below which a search counts as low-confidence, when `MIN_RELEVANCE_SCORE` isn't set.
+const DEFAULT_GAP_SCORE: f32 = 0.5;
+
+/// Cosine similarity a query needs to the centroid of a cluster to join it.
+const DEFAULT_GAP_SIMILARITY: f32 = 0.8;
+
+/// Documents listed per cluster as the closest the corpus currently has.
+const NEAREST_DOCS: usize = 3;
+
+#[derive(Debug, Deserialize)]
+struct GapsQuery {
+    /// Searches whose best score is below this count as unanswered.
+    max_score: Option<f32>,
+    /// Minimum similarity of queries grouped into one cluster.
+    similarity: Option<f32>,
+}
+
+/// A group of similar questions the corpus failed to answer.
+#[derive(Debug, Serialize)]
+struct GapCluster {
+    size: usize,
+    /// The query closest to the cluster's centroid.
+    representative: String,
+    queries: Vec<String>,
+    fallbacks: usize,
+    last_asked: DateTime<Utc>,
+    /// Documents most often retrieved for these queries.
+    nearest_docs: Vec<String>,
+}
+
+struct Cluster {
+    centroid: Vec<f32>,
+    records: Vec<SearchRecord>,
+}
+
+/// Reports low-confidence searches clustered by query embedding similarity, largest clusters first.
+///
+/// A search is low-confidence when the fallback answer fired, its best score is below
+/// `max_score` (`MIN_RELEVANCE_SCORE`, or 0.5 when unset) or its answer was rated down.
+#[get("/admin/gaps")]
+async fn gaps(query: Query<GapsQuery>) -> impl Responder {
+    let max_score = query
+        .max_score
+        .or(env::var("MIN_RELEVANCE_SCORE").ok().and_then(|s| s.parse().ok()))
+        .unwrap_or(DEFAULT_GAP_SCORE);
+    let similarity = query.similarity.unwrap_or(DEFAULT_GAP_SIMILARITY);
+
+    let entries = match web::block(read_entries).await {
+        Ok(Ok(e)) => e,
+        Ok(Err(e)) => return HttpResponse::InternalServerError().body(format!("{:#?}", e)),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("{:#?}", e)),
+    };
+
+    let rated_down: HashSet<String> = entries
+        .iter()
+        .filter_map(|e| match e {
+            LogEntry::Feedback(f) if f.rating == Rating::Down => Some(f.response_id.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let unanswered: Vec<SearchRecord> = entries
+        .into_iter()
+        .filter_map(|e| match e {
+            LogEntry::Search(record) => Some(record),
+            LogEntry::Answer(_) | LogEntry::Feedback(_) => None,
+        })
+        .filter(|r| r.fallback || r.best_score.is_none_or(|s| s < max_score) || rated_down.contains(&r.id))
+        .filter(|r| !r.query_embedding.is_empty())
+        .collect();
+
+    HttpResponse::Ok().json(cluster_gaps(unanswered, similarity))
+}
+
+/// Greedily assigns every record to the most similar cluster above `similarity`, or starts a new one.
+fn cluster_gaps(records: Vec<SearchRecord>, similarity: f32) -> Vec<GapCluster> {
+    let mut clusters: Vec<Cluster> = vec![];
+
+    for record in records {
+        let best = clusters
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (i, cosine(&c.centroid, &record.query_embedding)))
+            .filter(|(_, s)| *s >= similarity)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((index, _)) => {
+                let cluster = &mut clusters[index];
+                let n = cluster.records.len() as f32;
+                for (c, v) in cluster.centroid.iter_mut().zip(&record.query_embedding) {
+                    *c = (*c * n + v) / (n + 1.0);
+                }
+                cluster.records.push(record);
+            }
+            None => clusters.push(Cluster {
+                centroid: record.query_embedding.clone(),
+                records: vec![record],
+            }),
+        }
+    }
+
+    let mut report: Vec<GapCluster> = clusters.into_iter().map(summarize_cluster).collect();
+    report.sort_by(|a, b| b.size.cmp(&a.size).then(b.last_asked.cmp(&a.last_asked)));
+    report
+}
+
+fn summarize_cluster(cluster: Cluster) -> GapCluster {
+    let Cluster { centroid, records } = cluster;
+
+    let representative = records
+        .iter()
+        .max_by(|a, b| cosine(&centroid, &a.query_embedding).total_cmp(&cosine(&centroid, &b.query_embedding)))
+        .map(|r| r.query.clone())
+        .unwrap_or_default();
+
+    let mut queries: Vec<String> = vec![];
+    let mut doc_counts: HashMap<&str, usize> = HashMap::new();
+    for record in &records {
+        if !queries.contains(&record.query) {
+            queries.push(record.query.clone());
+        }
+        for doc_id in &record.doc_ids {
+            *doc_counts.entry(doc_id).or_insert(0) += 1;
+        }
+    }
+    let mut nearest_docs: Vec<(&str, usize)> = doc_counts.into_iter().collect();
+    nearest_docs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    GapCluster {
+        size: records.len(),
+        representative,
+        queries,
+        fallbacks: records.iter().filter(|r| r.fallback).count(),
+        last_asked: records.iter().map(|r| r.timestamp).max().unwrap_or_default(),
+        nearest_docs: nearest_docs.into_iter().take(NEAREST_DOCS).map(|(d, _)| d.to_string()).collect(),
+    }
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
@@ -0,0 +1,260 @@
+use actix_web::{get, web::{self, Query}, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, Closed, MessageStream, Session};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_stream::StreamExt;
+
+use crate::rag::{Rag, Role, SearchEvent, SearchOptions, Turn};
+
+use super::search_log::{self, SearchRecord};
+
+/// Turns of history kept per conversation and given to the model.
+const MAX_HISTORY_TURNS: usize = 12;
+
+/// Hours a conversation is kept after its last exchange.
+const CONVERSATION_TTL_HOURS: i64 = 24;
+
+/// Conversations kept at most, the least recently active ones are evicted first.
+const MAX_CONVERSATIONS: usize = 1_000;
+
+/// Messages of a connection waiting for their answer, further ones are rejected.
+const MAX_QUEUED_MESSAGES: usize = 4;
+
+/// In-memory chat histories, keyed by conversation id.
+///
+/// A conversation is only stored once its first exchange completed and is evicted after
+/// `CONVERSATION_TTL_HOURS` without activity or when more than `MAX_CONVERSATIONS` are kept.
+#[derive(Debug, Clone, Default)]
+pub struct Conversations {
+    histories: Arc<Mutex<HashMap<String, Conversation>>>,
+}
+
+#[derive(Debug)]
+struct Conversation {
+    turns: Vec<Turn>,
+    last_active: DateTime<Utc>,
+}
+
+impl Conversations {
+    /// Returns `id` when it names a known conversation, otherwise the id for a new one.
+    fn open(&self, id: Option<String>) -> String {
+        let mut histories = self.histories.lock().unwrap();
+        evict_stale(&mut histories);
+        match id {
+            Some(id) if histories.contains_key(&id) => id,
+            _ => uuid::Uuid::new_v4().to_string(),
+        }
+    }
+
+    fn history(&self, id: &str) -> Vec<Turn> {
+        self.histories.lock().unwrap().get(id).map(|c| c.turns.clone()).unwrap_or_default()
+    }
+
+    fn append(&self, id: &str, message: String, answer: String) {
+        let mut histories = self.histories.lock().unwrap();
+        let conversation = histories.entry(id.to_string()).or_insert_with(|| Conversation {
+            turns: vec![],
+            last_active: Utc::now(),
+        });
+        conversation.last_active = Utc::now();
+        let history = &mut conversation.turns;
+        history.push(Turn { role: Role::User, content: message });
+        history.push(Turn { role: Role::Assistant, content: answer });
+        if history.len() > MAX_HISTORY_TURNS {
+            history.drain(..history.len() - MAX_HISTORY_TURNS);
+        }
+        evict_stale(&mut histories);
+    }
+}
+
+/// Drops conversations idle for longer than the TTL, then the least recently active ones over the cap.
+fn evict_stale(histories: &mut HashMap<String, Conversation>) {
+    let cutoff = Utc::now() - Duration::hours(CONVERSATION_TTL_HOURS);
+    histories.retain(|_, conversation| conversation.last_active > cutoff);
+
+    if histories.len() > MAX_CONVERSATIONS {
+        let mut by_activity: Vec<(DateTime<Utc>, String)> = histories
+            .iter()
+            .map(|(id, conversation)| (conversation.last_active, id.clone()))
+            .collect();
+        by_activity.sort();
+        for (_, id) in by_activity.into_iter().take(histories.len() - MAX_CONVERSATIONS) {
+            histories.remove(&id);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatParams {
+    /// Resume an existing conversation instead of starting a new one.
+    conversation_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatMessage {
+    message: String,
+}
+
+/// Opens a WebSocket chat.
+///
+/// The server first sends `{"type": "conversation", "conversation_id": ...}`. Every text
+/// message (plain text or `{"message": ...}`) is answered with the same events as
+/// `/api/search` (`sources`, `delta`, `citations`, `followups`, `error`, `done`), one per
+/// frame. Messages sent while an answer is streaming are answered in order afterwards. The
+/// conversation history is kept on the server and used for retrieval and answering.
+#[get("/chat")]
+async fn chat(
+    req: HttpRequest,
+    body: web::Payload,
+    params: Query<ChatParams>,
+    conversations: web::Data<Conversations>,
+) -> actix_web::Result<HttpResponse> {
+    let (response, session, stream) = actix_ws::handle(&req, body)?;
+    let conversation_id = conversations.open(params.into_inner().conversation_id);
+    actix_web::rt::spawn(converse(session, stream, conversations.get_ref().clone(), conversation_id));
+    Ok(response)
+}
+
+/// Reads the client messages and hands them to a worker answering them one at a time, so
+/// pings keep being answered while an answer streams.
+async fn converse(mut session: Session, stream: MessageStream, conversations: Conversations, id: String) {
+    let mut stream = stream.aggregate_continuations();
+    if send(&mut session, json!({ "type": "conversation", "conversation_id": id })).await.is_err() {
+        return;
+    }
+
+    let (messages, mut queued) = mpsc::channel::<String>(MAX_QUEUED_MESSAGES);
+    let mut answering = session.clone();
+    let worker = actix_web::rt::spawn(async move {
+        let rag = Rag::default();
+        while let Some(message) = queued.recv().await {
+            if answer(&mut answering, &rag, &conversations, &id, message).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    while let Some(msg) = stream.recv().await {
+        let text = match msg {
+            Ok(AggregatedMessage::Text(text)) => text.to_string(),
+            Ok(AggregatedMessage::Ping(bytes)) => {
+                if session.pong(&bytes).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            Ok(AggregatedMessage::Close(_)) | Err(_) => break,
+            Ok(_) => continue,
+        };
+
+        let message = serde_json::from_str::<ChatMessage>(&text)
+            .map(|m| m.message)
+            .unwrap_or(text);
+        match messages.try_send(message) {
+            Ok(_) => (),
+            Err(TrySendError::Full(_)) => {
+                let busy = json!({ "type": "error", "message": "Too many messages waiting for an answer" });
+                if send(&mut session, busy).await.is_err() {
+                    break;
+                }
+            }
+            Err(TrySendError::Closed(_)) => break,
+        }
+    }
+
+    worker.abort();
+    let _ = session.close(None).await;
+}
+
+/// Streams the answer to `message` and records the exchange once it completed.
+async fn answer(session: &mut Session, rag: &Rag, conversations: &Conversations, id: &str, message: String) -> Result<(), Closed> {
+    let history = conversations.history(id);
+    let mut result = match rag.chat(&history, message.clone(), SearchOptions::default()).await {
+        Ok(r) => r,
+        Err(e) => return send(session, json!({ "type": "error", "message": e.to_string() })).await,
+    };
+
+    let record = SearchRecord::new(&message, &result);
+    let response_id = record.id.clone();
+    if let Err(e) = search_log::log_search(record) {
+        eprintln!("Failed writing the search log: {:?}", e);
+    }
+    send(session, json!({
+        "type": "sources",
+        "response_id": response_id,
+        "query": result.query,
+        "expanded_queries": result.expanded_queries,
+        "hypothetical_answer": result.hypothetical_answer,
+        "fallback": result.fallback,
+        "suggestions": result.suggestions,
+        "chunks": result.chunks,
+        "dropped": result.dropped,
+    })).await?;
+
+    let mut answer = String::new();
+    let mut completed = false;
+    while let Some(event) = result.stream.next().await {
+        match &event {
+            SearchEvent::Delta { text } => answer.push_str(text),
+            SearchEvent::Done { .. } => completed = true,
+            _ => {}
+        }
+        if let Ok(value) = serde_json::to_value(&event) {
+            send(session, value).await?;
+        }
+    }
+
+    if let Err(e) = search_log::log_answer(&response_id, answer.clone()) {
+        eprintln!("Failed writing the search log: {:?}", e);
+    }
+    if completed {
+        conversations.append(id, message, answer);
+    }
+    Ok(())
+}
+
+async fn send(session: &mut Session, value: Value) -> Result<(), Closed> {
+    session.text(value.to_string()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_does_not_store_new_conversations() {
+        let conversations = Conversations::default();
+        let id = conversations.open(None);
+        assert!(conversations.histories.lock().unwrap().is_empty());
+        assert_ne!(conversations.open(Some(id.clone())), id);
+
+        conversations.append(&id, "hi".into(), "hello".into());
+        assert_eq!(conversations.open(Some(id.clone())), id);
+        assert_eq!(conversations.history(&id).len(), 2);
+    }
+
+    #[test]
+    fn evicts_idle_and_least_recently_active_conversations() {
+        let conversations = Conversations::default();
+        {
+            let mut histories = conversations.histories.lock().unwrap();
+            let idle = Utc::now() - Duration::hours(CONVERSATION_TTL_HOURS + 1);
+            histories.insert("idle".into(), Conversation { turns: vec![], last_active: idle });
+            for i in 0..MAX_CONVERSATIONS {
+                let last_active = Utc::now() - Duration::minutes(i as i64 + 1);
+                histories.insert(format!("c{}", i), Conversation { turns: vec![], last_active });
+            }
+        }
+
+        conversations.append("new", "hi".into(), "hello".into());
+
+        let histories = conversations.histories.lock().unwrap();
+        assert_eq!(histories.len(), MAX_CONVERSATIONS);
+        assert!(histories.contains_key("new"));
+        assert!(!histories.contains_key("idle"));
+        assert!(!histories.contains_key(&format!("c{}", MAX_CONVERSATIONS - 1)));
+    }
+}
@@ -0,0 +1,270 @@
+use actix_multipart::{Field, Multipart};
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use tokio::{fs::File, io::AsyncWriteExt};
+use tokio_stream::StreamExt;
+
+use crate::rag::{Rag, RagProcessableFile};
+
+use super::{file_type, files_folder, jobs::{JobQueue, QueuedFile}, JobCreated};
+
+/// Files and form fields of a document upload.
+struct Upload {
+    files: Vec<(String, PathBuf)>,
+    file_description: Option<String>,
+    tags: Option<Vec<String>>,
+    doc_id: Option<String>,
+}
+
+/// Lists every document in the index with its summary, tags and chunk and question counts.
+#[get("/documents")]
+async fn list() -> impl Responder {
+    let rag = Rag::default();
+    match rag.documents().await {
+        Ok(documents) => HttpResponse::Ok().json(documents),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:#?}", e)),
+    }
+}
+
+/// Returns the chunks of a document together with their generated HyPE questions.
+#[get("/documents/{doc_id}/chunks")]
+async fn chunks(doc_id: web::Path<String>) -> impl Responder {
+    let rag = Rag::default();
+    match rag.document_chunks(&doc_id).await {
+        Ok(chunks) if chunks.is_empty() => HttpResponse::NotFound().finish(),
+        Ok(chunks) => HttpResponse::Ok().json(chunks),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:#?}", e)),
+    }
+}
+
+/// Accepts a `multipart/form-data` upload of one or more documents.
+///
+/// Every part carrying a filename is stored under `FILES_FOLDER` and queued for ingestion.
+/// A document is identified by its file name unless the optional `doc_id` field is given,
+/// which is only accepted together with a single file. Uploading under an existing id
+/// replaces that document. The optional `file_description` and `tags` (comma separated)
+/// fields apply to all uploaded files of the request. Responds with the id of the ingestion job.
+#[post("/documents")]
+async fn upload(payload: Multipart, queue: web::Data<JobQueue>) -> impl Responder {
+    let Upload { files, file_description, tags, doc_id } = match read_upload(payload).await {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+
+    if doc_id.is_some() && files.len() != 1 {
+        discard(files.into_iter().map(|(_, path)| path)).await;
+        return HttpResponse::BadRequest().body("A doc_id can only be given when uploading a single file");
+    }
+
+    let targets: Option<Vec<(String, PathBuf, String, PathBuf)>> = files
+        .iter()
+        .map(|(file_name, upload_path)| {
+            let internal_id = doc_id.clone().unwrap_or(file_name.clone());
+            let path = stored_path(&internal_id)?;
+            Some((file_name.clone(), upload_path.clone(), internal_id, path))
+        })
+        .collect();
+    let Some(targets) = targets else {
+        discard(files.into_iter().map(|(_, path)| path)).await;
+        return HttpResponse::BadRequest().body("Invalid document id");
+    };
+
+    let mut queued = vec![];
+    let mut targets = targets.into_iter();
+    while let Some((file_name, upload_path, internal_id, path)) = targets.next() {
+        if let Err(e) = tokio::fs::rename(&upload_path, &path).await {
+            eprintln!("Failed to store uploaded file '{}': {:?}", file_name, e);
+            discard(std::iter::once(upload_path).chain(targets.map(|(_, upload_path, _, _)| upload_path))).await;
+            // Files stored so far replaced their documents' files, so they are still ingested.
+            if !queued.is_empty() {
+                queue.enqueue(queued);
+            }
+            return HttpResponse::InternalServerError().finish();
+        }
+
+        queued.push(QueuedFile {
+            file: processable_file(file_name, path, internal_id, file_description.clone(), tags.clone()),
+            done_dir: None,
+            replace: true,
+        });
+    }
+
+    let job_id = queue.enqueue(queued);
+    HttpResponse::Accepted().json(JobCreated { job_id })
+}
+
+/// Re-indexes the document `doc_id` from a new version uploaded as `multipart/form-data`.
+///
+/// Exactly one file is expected. Once ingested, it replaces all existing chunks of the document.
+#[put("/documents/{doc_id}")]
+async fn replace(doc_id: web::Path<String>, payload: Multipart, queue: web::Data<JobQueue>) -> impl Responder {
+    let Upload { mut files, file_description, tags, .. } = match read_upload(payload).await {
+        Ok(u) => u,
+        Err(resp) => return resp,
+    };
+
+    if files.len() != 1 {
+        discard(files.into_iter().map(|(_, path)| path)).await;
+        return HttpResponse::BadRequest().body("Exactly one file is expected when replacing a document");
+    }
+    let (file_name, upload_path) = files.remove(0);
+    let Some(path) = stored_path(&doc_id) else {
+        discard([upload_path]).await;
+        return HttpResponse::BadRequest().body("Invalid document id");
+    };
+    if let Err(e) = tokio::fs::rename(&upload_path, &path).await {
+        eprintln!("Failed to store replacement of '{}': {:?}", doc_id, e);
+        discard([upload_path]).await;
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let job_id = queue.enqueue(vec![QueuedFile {
+        file: processable_file(file_name, path, doc_id.into_inner(), file_description, tags),
+        done_dir: None,
+        replace: true,
+    }]);
+    HttpResponse::Accepted().json(JobCreated { job_id })
+}
+
+/// Removes the document `doc_id` from the index together with its stored upload.
+///
+/// Responds with 404 when the index holds no chunks for `doc_id`.
+#[delete("/documents/{doc_id}")]
+async fn delete(doc_id: web::Path<String>) -> impl Responder {
+    let rag = Rag::default();
+    match rag.delete(&doc_id).await {
+        Ok(false) => return HttpResponse::NotFound().finish(),
+        Ok(true) => (),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("{:#?}", e)),
+    }
+
+    if let Some(path) = stored_path(&doc_id) {
+        match tokio::fs::remove_file(&path).await {
+            Ok(_) => println!("Removed stored file {:?}", path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => eprintln!("Failed to remove stored file {:?}: {:?}", path, e),
+        }
+    }
+    HttpResponse::NoContent().finish()
+}
+
+/// Path of the uploaded file backing `doc_id` under `FILES_FOLDER`.
+///
+/// Returns `None` for ids that are not a plain file name, so they can't escape the folder.
+fn stored_path(doc_id: &str) -> Option<PathBuf> {
+    let name = Path::new(doc_id).file_name()?;
+    if name != doc_id {
+        return None;
+    }
+    Some(Path::new(&files_folder()).join(name))
+}
+
+/// The loader is picked from the name the file was uploaded with, `path` is only where it is stored.
+fn processable_file(
+    file_name: String,
+    path: PathBuf,
+    internal_id: String,
+    file_description: Option<String>,
+    tags: Option<Vec<String>>,
+) -> RagProcessableFile {
+    RagProcessableFile {
+        file_type: file_type(Path::new(&file_name)),
+        path,
+        internal_id,
+        original_name: file_name,
+        file_description,
+        tags,
+    }
+}
+
+/// Stores every file of the multipart payload under `FILES_FOLDER` and collects the
+/// `file_description`, `tags` and `doc_id` form fields.
+async fn read_upload(mut payload: Multipart) -> Result<Upload, HttpResponse> {
+    let mut files: Vec<(String, PathBuf)> = vec![];
+    let mut file_description = None;
+    let mut tags = None;
+    let mut doc_id = None;
+
+    while let Some(field) = payload.next().await {
+        let Ok(mut field) = field else {
+            discard(files.into_iter().map(|(_, path)| path)).await;
+            return Err(HttpResponse::BadRequest().body("Malformed multipart payload"));
+        };
+
+        let field_name = field.name().unwrap_or("").to_string();
+        let file_name = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .and_then(|name| Path::new(name).file_name())
+            .map(|name| name.to_string_lossy().to_string());
+
+        match (field_name.as_str(), file_name) {
+            (_, Some(file_name)) => {
+                let stored_name = format!("{}_{}", uuid::Uuid::new_v4(), file_name);
+                let path = Path::new(&files_folder()).join(&stored_name);
+                if let Err(e) = save_field(&mut field, &path).await {
+                    eprintln!("Failed to store uploaded file '{}': {:?}", file_name, e);
+                    discard(files.into_iter().map(|(_, path)| path).chain([path])).await;
+                    return Err(HttpResponse::InternalServerError().finish());
+                }
+                files.push((file_name, path));
+            }
+            ("file_description", None) => {
+                file_description = Some(read_field(&mut field).await);
+            }
+            ("tags", None) => {
+                let parsed: Vec<String> = read_field(&mut field)
+                    .await
+                    .split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect();
+                tags = Some(parsed);
+            }
+            ("doc_id", None) => {
+                doc_id = Some(read_field(&mut field).await).filter(|id| !id.is_empty());
+            }
+            _ => {
+                eprintln!("Skipping unknown multipart field: {:?}", field_name);
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return Err(HttpResponse::BadRequest().body("No files were uploaded"));
+    }
+
+    Ok(Upload { files, file_description, tags, doc_id })
+}
+
+/// Removes uploaded files that won't be ingested.
+async fn discard<I>(paths: I) where I: IntoIterator<Item = PathBuf> {
+    for path in paths {
+        match tokio::fs::remove_file(&path).await {
+            Ok(_) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => eprintln!("Failed to remove uploaded file {:?}: {:?}", path, e),
+        }
+    }
+}
+
+async fn save_field(field: &mut Field, path: &Path) -> Result<()> {
+    let mut file = File::create(path).await?;
+    while let Some(chunk) = field.next().await {
+        let bytes = chunk.map_err(|e| anyhow!(e.to_string()))?;
+        file.write_all(&bytes).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+async fn read_field(field: &mut Field) -> String {
+    let mut buffer = vec![];
+    while let Some(chunk) = field.next().await {
+        if let Ok(bytes) = chunk {
+            buffer.extend_from_slice(&bytes);
+        }
+    }
+    String::from_utf8_lossy(&buffer).trim().to_string()
+}
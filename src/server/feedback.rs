@@ -0,0 +1,50 @@
+use actix_web::{post, web, HttpResponse, Responder};
+use chrono::Utc;
+use serde::Deserialize;
+
+use super::search_log::{self, FeedbackRecord, LogEntry, Rating};
+
+#[derive(Debug, Deserialize)]
+struct FeedbackRequest {
+    /// The `response_id` sent with the `sources` event of the answer.
+    response_id: String,
+    rating: Rating,
+    comment: Option<String>,
+    /// Id of a cited chunk that was wrong or irrelevant.
+    wrong_chunk_id: Option<String>,
+}
+
+/// Stores feedback on an answer together with the query, retrieved chunks and answer it was given for.
+///
+/// The response id is known as soon as the `sources` event was sent. Feedback given while
+/// the answer is still streaming is stored without the answer. Only the most recent
+/// responses can be rated, older ids get a 404.
+#[post("/feedback")]
+async fn feedback(request: web::Json<FeedbackRequest>) -> impl Responder {
+    let FeedbackRequest { response_id, rating, comment, wrong_chunk_id } = request.into_inner();
+
+    let Some(search) = search_log::find_response(&response_id) else {
+        return HttpResponse::NotFound().body(format!("Unknown response id '{}'", response_id));
+    };
+
+    if let Some(chunk_id) = &wrong_chunk_id {
+        if !search.chunk_ids.contains(chunk_id) {
+            return HttpResponse::BadRequest().body(format!("Chunk '{}' wasn't part of the response", chunk_id));
+        }
+    }
+
+    let record = FeedbackRecord {
+        response_id,
+        timestamp: Utc::now(),
+        rating,
+        comment: comment.filter(|c| !c.trim().is_empty()),
+        wrong_chunk_id,
+        query: search.query,
+        chunk_ids: search.chunk_ids,
+        answer: search.answer,
+    };
+    match search_log::append(&LogEntry::Feedback(record)) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(format!("{:#?}", e)),
+    }
+}
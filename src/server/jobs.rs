@@ -0,0 +1,189 @@
+use actix_web::{get, web, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::{collections::HashMap, fs, path::PathBuf, sync::{Arc, Mutex}, time::Instant};
+use tokio::sync::mpsc;
+
+use crate::rag::{IngestStage, Rag, RagProcessableFile};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state", content = "error")]
+pub enum FileState {
+    Queued,
+    Loading,
+    Chunking,
+    Hype,
+    Embedding,
+    Upserting,
+    Done,
+    Failed(String),
+}
+
+impl From<IngestStage> for FileState {
+    fn from(stage: IngestStage) -> Self {
+        match stage {
+            IngestStage::Loading => FileState::Loading,
+            IngestStage::Chunking => FileState::Chunking,
+            IngestStage::Hype => FileState::Hype,
+            IngestStage::Embedding => FileState::Embedding,
+            IngestStage::Upserting => FileState::Upserting,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobFile {
+    pub internal_id: String,
+    pub original_name: String,
+    #[serde(flatten)]
+    pub state: FileState,
+}
+
+/// How long finished jobs stay queryable before they are evicted.
+const JOB_RETENTION_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    /// Set once every file of the job is done or failed.
+    pub finished_at: Option<DateTime<Utc>>,
+    pub files: Vec<JobFile>,
+}
+
+/// A file waiting to be ingested by the worker.
+///
+/// When `replace` is set the existing points of the document are swapped out through
+/// `Rag::reindex_with_progress`. When `done_dir` is set the file is moved there after a successful insert.
+#[derive(Debug)]
+pub struct QueuedFile {
+    pub file: RagProcessableFile,
+    pub done_dir: Option<PathBuf>,
+    pub replace: bool,
+}
+
+#[derive(Debug)]
+struct QueueItem {
+    job_id: String,
+    index: usize,
+    queued: QueuedFile,
+}
+
+/// In-process ingestion queue.
+///
+/// Handlers enqueue files and get a job id back immediately, while a single background
+/// worker runs them through `Rag::insert_with_progress` one at a time and records the
+/// state of every file.
+#[derive(Debug, Clone)]
+pub struct JobQueue {
+    jobs: Arc<Mutex<HashMap<String, Job>>>,
+    sender: mpsc::UnboundedSender<QueueItem>,
+}
+
+impl JobQueue {
+    pub fn start() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let queue = Self {
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            sender,
+        };
+        tokio::spawn(queue.clone().work(receiver));
+        queue
+    }
+
+    pub fn enqueue(&self, files: Vec<QueuedFile>) -> String {
+        let job_id = uuid::Uuid::new_v4().to_string();
+        let job = Job {
+            id: job_id.clone(),
+            created_at: Utc::now(),
+            finished_at: None,
+            files: files
+                .iter()
+                .map(|q| JobFile {
+                    internal_id: q.file.internal_id.clone(),
+                    original_name: q.file.original_name.clone(),
+                    state: FileState::Queued,
+                })
+                .collect(),
+        };
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            evict_finished(&mut jobs);
+            jobs.insert(job_id.clone(), job);
+        }
+
+        for (index, queued) in files.into_iter().enumerate() {
+            let item = QueueItem { job_id: job_id.clone(), index, queued };
+            if let Err(e) = self.sender.send(item) {
+                eprintln!("Ingestion worker is gone, can't enqueue file: {:?}", e.0.queued.file.original_name);
+                self.set_state(&job_id, index, FileState::Failed("Ingestion worker is not running".into()));
+            }
+        }
+        job_id
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<Job> {
+        self.jobs.lock().unwrap().get(job_id).cloned()
+    }
+
+    fn set_state(&self, job_id: &str, index: usize, state: FileState) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(job) = jobs.get_mut(job_id) else {
+            return;
+        };
+        if let Some(file) = job.files.get_mut(index) {
+            file.state = state;
+        }
+        let finished = job.files.iter().all(|f| matches!(f.state, FileState::Done | FileState::Failed(_)));
+        if finished && job.finished_at.is_none() {
+            job.finished_at = Some(Utc::now());
+        }
+    }
+
+    async fn work(self, mut receiver: mpsc::UnboundedReceiver<QueueItem>) {
+        let rag = Rag::default();
+        while let Some(QueueItem { job_id, index, queued }) = receiver.recv().await {
+            let QueuedFile { file, done_dir, replace } = queued;
+            let file_name = file.original_name.clone();
+            let path = file.path.clone();
+            let start_time = Instant::now();
+
+            let on_stage = |stage: IngestStage| self.set_state(&job_id, index, stage.into());
+            let result = if replace {
+                rag.reindex_with_progress(file, on_stage).await
+            } else {
+                rag.insert_with_progress(file, on_stage).await
+            };
+
+            match result {
+                Ok(_) => {
+                    println!("Successfully inserted file '{}' in {:?}", file_name, start_time.elapsed());
+                    self.set_state(&job_id, index, FileState::Done);
+                    if let Some(done_dir) = done_dir {
+                        if let Err(e) = fs::rename(&path, done_dir.join(&file_name)) {
+                            eprintln!("Failed to move '{}' to done: {}", file_name, e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to insert file '{}' in {:?}: {:?}", file_name, start_time.elapsed(), e);
+                    self.set_state(&job_id, index, FileState::Failed(e.to_string()));
+                }
+            }
+        }
+    }
+}
+
+/// Drops jobs that finished more than `JOB_RETENTION_HOURS` ago.
+fn evict_finished(jobs: &mut HashMap<String, Job>) {
+    let cutoff = Utc::now() - Duration::hours(JOB_RETENTION_HOURS);
+    jobs.retain(|_, job| job.finished_at.is_none_or(|f| f > cutoff));
+}
+
+#[get("/jobs/{id}")]
+async fn job_status(queue: web::Data<JobQueue>, id: web::Path<String>) -> impl Responder {
+    match queue.get(&id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
@@ -1,60 +1,243 @@
 use actix_cors::Cors;
 use actix_web::{
-    get, web::{self, Bytes, Query}, App, HttpResponse, HttpServer, Responder
+    get, http::header, web::{self, Bytes, Query}, App, HttpRequest, HttpResponse, HttpServer, Responder
 };
-use serde::Deserialize;
-use std::{convert::Infallible, env, fs::{self, create_dir_all, File}, io::Read, sync::Mutex, time::Instant};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, env, fs::{self, create_dir_all, File}, io::Read, path::Path, sync::Mutex, time::Instant};
 use tokio::sync::mpsc;
 use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 
-use crate::rag::{Rag, RagProcessableFile, RagProcessableFileType};
+use crate::rag::{QueryMode, Rag, RagProcessableFile, RagProcessableFileType, SearchEvent, SearchFilter, SearchOptions};
+use jobs::{JobQueue, QueuedFile};
+use search_log::SearchRecord;
+
+mod admin;
+mod chat;
+mod documents;
+mod feedback;
+mod jobs;
+mod search_log;
+
+/// Upper bound for `top_k`, Qdrant is asked for up to three times as many points with MMR.
+const MAX_TOP_K: u64 = 100;
+
+/// Upper bound for `neighbours`, every hit pulls up to twice as many chunks from Qdrant.
+const MAX_NEIGHBOURS: i32 = 5;
 
 #[derive(Debug, Deserialize)]
 struct SearchQuery {
     query: String,
+    /// Number of chunks to retrieve, between 1 and `MAX_TOP_K`.
+    top_k: Option<u64>,
+    score_threshold: Option<f32>,
+    /// Comma separated list of tags, a chunk has to carry at least one of them.
+    tags: Option<String>,
+    /// Filter expression, e.g. `tags contains "erasmus" AND file_type = Pdf`.
+    filter: Option<String>,
+    /// Weight of the embedding ranking in the hybrid fusion.
+    dense_weight: Option<f32>,
+    /// Weight of the BM25 keyword ranking in the hybrid fusion, `0` turns keyword search off.
+    keyword_weight: Option<f32>,
+    /// Rerank the retrieved chunks and keep this many of the best ones, between 1 and `MAX_TOP_K`.
+    rerank: Option<usize>,
+    /// Number of neighbouring chunks on each side of a hit to include in its passage, between 0 and `MAX_NEIGHBOURS`.
+    neighbours: Option<i32>,
+    /// Maximal marginal relevance trade-off between relevance (`1`) and diversity (`0`).
+    mmr_lambda: Option<f32>,
+    /// Number of paraphrases or sub-questions to search for in addition to the query.
+    expansions: Option<usize>,
+    /// `question` (default), `hyde` or `hyde_average`.
+    query_mode: Option<QueryMode>,
+    /// Check the answer against the retrieved chunks.
+    #[serde(default)]
+    verify: bool,
+    /// Withhold answers whose grounding score is below this, implies `verify`.
+    grounding_threshold: Option<f32>,
+    /// Answer with the fallback when the best chunk scores below this, defaults to `MIN_RELEVANCE_SCORE`.
+    min_relevance: Option<f32>,
 }
 
+impl SearchQuery {
+    fn options(&self) -> anyhow::Result<SearchOptions> {
+        let defaults = SearchOptions::default();
+        let filter = match &self.filter {
+            Some(f) if !f.trim().is_empty() => Some(f.parse::<SearchFilter>()?),
+            _ => None,
+        };
+        if let Some(top_k) = self.top_k {
+            if !(1..=MAX_TOP_K).contains(&top_k) {
+                return Err(anyhow::anyhow!("top_k has to be between 1 and {}, got {}", MAX_TOP_K, top_k));
+            }
+        }
+        if let Some(rerank) = self.rerank {
+            if !(1..=MAX_TOP_K as usize).contains(&rerank) {
+                return Err(anyhow::anyhow!("rerank has to be between 1 and {}, got {}", MAX_TOP_K, rerank));
+            }
+        }
+        if let Some(neighbours) = self.neighbours {
+            if !(0..=MAX_NEIGHBOURS).contains(&neighbours) {
+                return Err(anyhow::anyhow!("neighbours has to be between 0 and {}, got {}", MAX_NEIGHBOURS, neighbours));
+            }
+        }
+        if let Some(threshold) = self.grounding_threshold {
+            if !(0.0..=1.0).contains(&threshold) {
+                return Err(anyhow::anyhow!("grounding_threshold has to be between 0 and 1, got {}", threshold));
+            }
+        }
+        if let Some(lambda) = self.mmr_lambda {
+            if !(0.0..=1.0).contains(&lambda) {
+                return Err(anyhow::anyhow!("mmr_lambda has to be between 0 and 1, got {}", lambda));
+            }
+        }
+        Ok(SearchOptions {
+            top_k: self.top_k.unwrap_or(defaults.top_k),
+            score_threshold: self.score_threshold,
+            tags: self
+                .tags
+                .as_deref()
+                .map(|t| t
+                    .split(',')
+                    .map(|tag| tag.trim().to_string())
+                    .filter(|tag| !tag.is_empty())
+                    .collect())
+                .unwrap_or_default(),
+            filter,
+            dense_weight: self.dense_weight.unwrap_or(defaults.dense_weight),
+            keyword_weight: self.keyword_weight.unwrap_or(defaults.keyword_weight),
+            rerank_top_n: self.rerank,
+            neighbours: self.neighbours.unwrap_or(defaults.neighbours),
+            mmr_lambda: self.mmr_lambda,
+            query_expansions: self.expansions.unwrap_or(defaults.query_expansions),
+            query_mode: self.query_mode.unwrap_or(defaults.query_mode),
+            verify_grounding: self.verify,
+            grounding_threshold: self.grounding_threshold,
+            min_relevance: self.min_relevance.or(defaults.min_relevance),
+        })
+    }
+}
+
+/// Answers the query from the indexed documents as a stream of events.
+///
+/// The `sources` event with the response id (for `/api/feedback`) and the retrieved and
+/// dropped chunks comes first, followed by answer `delta`s, the `citations`, `followups` and
+/// `grounding` and a final `done` (or `error`). Events are sent as newline delimited JSON, or
+/// as Server-Sent Events (with `delta` named `token`) when the client accepts
+/// `text/event-stream`.
 #[get("/search")]
-async fn search(search_query: Query<SearchQuery>) -> impl Responder {
+async fn search(req: HttpRequest, search_query: Query<SearchQuery>) -> impl Responder {
+    let start_time = Instant::now();
+    let sse = accepts_event_stream(&req);
+    let options = match search_query.options() {
+        Ok(o) => o,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
     let rag = Rag::default();
-    let mut result = match rag.search(search_query.query.clone()).await {
+    let mut result = match rag.search(search_query.query.clone(), options).await {
         Ok(r) => r,
         Err(e) => return HttpResponse::InternalServerError()
             .body(format!("{:#?}", e)),
     };
+    let retrieval_ms = start_time.elapsed().as_millis() as u64;
+    let record = SearchRecord::new(&search_query.query, &result);
+    let response_id = record.id.clone();
+    if let Err(e) = search_log::log_search(record) {
+        eprintln!("Failed writing the search log: {:?}", e);
+    }
 
     let (tx, rx) = mpsc::channel::<Result<Bytes, Infallible>>(10000);
     let stream = ReceiverStream::new(rx);
 
-    let Ok(chunks_json) = serde_json::to_string(&result.chunks) else {
-        return HttpResponse::InternalServerError().finish();
-    };
-    let _ = tx.send(Bytes::try_from(chunks_json)).await;
-    let _ = tx.send(Bytes::try_from("\n")).await;
+    let sources = serde_json::json!({
+        "type": "sources",
+        "response_id": response_id,
+        "query": result.query,
+        "expanded_queries": result.expanded_queries,
+        "hypothetical_answer": result.hypothetical_answer,
+        "fallback": result.fallback,
+        "suggestions": result.suggestions,
+        "chunks": result.chunks,
+        "dropped": result.dropped,
+    });
+    let _ = tx.send(Ok(event_bytes(sse, sources))).await;
 
     actix_web::rt::spawn(async move {
-        while let Some(res) = result.stream.next().await {
-            if let Ok(responses) = res {
-                for resp in responses {
-                    let data = Bytes::copy_from_slice(resp.response.as_bytes());
-                    let _ = tx.send(Ok(data)).await;
-                }
+        let mut answer = String::new();
+        while let Some(event) = result.stream.next().await {
+            if let SearchEvent::Delta { text } = &event {
+                answer.push_str(text);
             }
+            let Ok(mut data) = serde_json::to_value(&event) else {
+                continue;
+            };
+            if let (SearchEvent::Done { .. }, Some(fields)) = (&event, data.as_object_mut()) {
+                fields.insert("retrieval_ms".into(), retrieval_ms.into());
+                fields.insert("elapsed_ms".into(), (start_time.elapsed().as_millis() as u64).into());
+            }
+            if tx.send(Ok(event_bytes(sse, data))).await.is_err() {
+                break;
+            }
+        }
+
+        if let Err(e) = search_log::log_answer(&response_id, answer) {
+            eprintln!("Failed writing the search log: {:?}", e);
         }
     });
 
-    HttpResponse::Ok().content_type("text/plain").streaming(stream)
+    if sse {
+        HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            .streaming(stream)
+    } else {
+        HttpResponse::Ok().content_type("application/x-ndjson").streaming(stream)
+    }
 }
 
+fn accepts_event_stream(req: &HttpRequest) -> bool {
+    req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|a| a.to_str().ok())
+        .is_some_and(|a| a.contains("text/event-stream"))
+}
 
+/// Encodes a search event as a JSON line, or as an SSE frame named after its `type`.
+fn event_bytes(sse: bool, mut data: serde_json::Value) -> Bytes {
+    if !sse {
+        return Bytes::from(format!("{}\n", data));
+    }
+
+    let kind = data
+        .as_object_mut()
+        .and_then(|fields| fields.remove("type"))
+        .and_then(|t| t.as_str().map(|t| t.to_string()))
+        .unwrap_or("message".into());
+    let event = if kind == "delta" { "token".to_string() } else { kind };
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+
+
+#[derive(Debug, Serialize)]
+struct JobCreated {
+    job_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BuildResponse {
+    job_id: String,
+    skipped: Vec<String>,
+}
 
 #[derive(Debug, Deserialize)]
 struct BuildQuery {
     query: String,
+    #[serde(default)]
+    force: bool,
 }
 
 #[get("/build")]
-async fn build(search_query: Query<BuildQuery>) -> impl Responder {
+async fn build(search_query: Query<BuildQuery>, queue: web::Data<JobQueue>) -> impl Responder {
     let rag = Rag::default();
 
     let input_dir = &search_query.query;
@@ -73,7 +256,9 @@ async fn build(search_query: Query<BuildQuery>) -> impl Responder {
         return HttpResponse::InternalServerError().finish();
     };
 
-    for (id, entry) in files.enumerate() {
+    let mut queued = vec![];
+    let mut skipped = vec![];
+    for entry in files {
         let Ok(entry) = entry else {
             eprintln!("Skipping file, can't find? {:?}", entry);
             continue;
@@ -91,61 +276,68 @@ async fn build(search_query: Query<BuildQuery>) -> impl Responder {
             }
         };
 
-        let extension = path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .unwrap_or("")
-            .to_lowercase();
-
-        let file_type = match extension.as_str() {
-            "pdf" => RagProcessableFileType::Pdf,
-            "md" => RagProcessableFileType::Markdown,
-            "txt" => RagProcessableFileType::Text,
-            _ => RagProcessableFileType::Text,
-            
-        };
-
         let woodstock_data = RagProcessableFile {
             path: path.clone(),
-            file_type,
-            internal_id: format!("{}_{}", id, file_name),
+            file_type: file_type(&path),
+            internal_id: file_name.clone(),
             original_name: file_name.clone(),
-            tags: Some(vec![to_link(file_name.clone())]),
+            tags: to_link(&file_name).map(|link| vec![link]),
             file_description: None,
         };
 
-        let start_time = Instant::now();
-
-        match rag.insert(woodstock_data).await {
-            Ok(_) => {
-                let duration = start_time.elapsed();
-                println!("Successfully inserted file '{}' in {:?}", file_name, duration);
-                let done_path = format!("{}/{}", done_dir, file_name);
-                if let Err(e) = fs::rename(&path, &done_path) {
-                    eprintln!("Failed to move '{}' to done: {}", file_name, e);
+        if !search_query.force {
+            match rag.is_unchanged(&woodstock_data).await {
+                Ok(true) => {
+                    println!("Skipping unchanged file '{}'", file_name);
+                    skipped.push(file_name);
+                    continue;
                 }
-            }
-            Err(e) => {
-                let duration = start_time.elapsed();
-                eprintln!("Failed to insert file '{}' in {:?}: {:?}", file_name, duration, e);
+                Ok(false) => (),
+                Err(e) => eprintln!("Failed checking whether '{}' changed: {:?}", file_name, e),
             }
         }
+
+        queued.push(QueuedFile {
+            file: woodstock_data,
+            done_dir: Some(done_dir.into()),
+            replace: true,
+        });
+    }
+
+    let job_id = queue.enqueue(queued);
+    HttpResponse::Accepted().json(BuildResponse { job_id, skipped })
+}
+
+fn file_type(path: &Path) -> RagProcessableFileType {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    match extension.as_str() {
+        "pdf" => RagProcessableFileType::Pdf,
+        "md" => RagProcessableFileType::Markdown,
+        "txt" => RagProcessableFileType::Text,
+        _ => RagProcessableFileType::Text,
     }
+}
 
-    HttpResponse::Ok().into()
+fn files_folder() -> String {
+    env::var("FILES_FOLDER").unwrap_or("/var/woodstock/files".to_string())
 }
 
-fn to_link(name: String) -> String {
+/// Recovers the source URL from the name of a scraped page, `None` for ordinary files.
+fn to_link(name: &str) -> Option<String> {
     if !name.starts_with("https:") {
-        return "None".into();
+        return None;
     }
 
-    name
+    Some(name
         .replace(":_", "://")
         .replace("_", "/")
         .replace(".md_translated", "")
-        .replace(".md", "")
-        
+        .replace(".md", ""))
 }
 pub async fn start_server() {
     let server_port = env::var("SERVER_PORT")
@@ -153,22 +345,62 @@ pub async fn start_server() {
         .and_then(|x| x.parse::<u16>().ok())
         .unwrap_or(6969);
     
-    create_dir_all(env::var("FILES_FOLDER")
-        .unwrap_or("/var/woodstock/files".to_string()))
+    create_dir_all(files_folder())
         .expect("Unable to create the files folder.");
 
+    if let Err(e) = search_log::load_recent_responses() {
+        eprintln!("Failed reading the search log, feedback on earlier answers will be rejected: {:?}", e);
+    }
+
+    let queue = web::Data::new(JobQueue::start());
+    let conversations = web::Data::new(chat::Conversations::default());
+
     println!("Server is running on localhost:{}", server_port);
     let _ = HttpServer::new(move || {
         let cors = Cors::permissive();
         App::new()
             .wrap(cors)
+            .app_data(queue.clone())
+            .app_data(conversations.clone())
             .service(web::scope("/api")
                 .service(search)
+                .service(chat::chat)
                 .service(build)
+                .service(documents::list)
+                .service(documents::chunks)
+                .service(documents::upload)
+                .service(documents::replace)
+                .service(documents::delete)
+                .service(jobs::job_status)
+                .service(admin::gaps)
+                .service(feedback::feedback)
             )
     })
     .bind(("localhost", server_port))
     .expect("Unable to start the server")
     .run()
     .await;
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(query: &str) -> anyhow::Result<SearchOptions> {
+        Query::<SearchQuery>::from_query(query).unwrap().options()
+    }
+
+    #[test]
+    fn bounds_neighbours() {
+        assert_eq!(options("query=q&neighbours=2").unwrap().neighbours, 2);
+        assert!(options(&format!("query=q&neighbours={}", MAX_NEIGHBOURS + 1)).is_err());
+        assert!(options("query=q&neighbours=-1").is_err());
+        assert!(options(&format!("query=q&neighbours={}", i32::MAX)).is_err());
+    }
+
+    #[test]
+    fn rejects_rerank_below_one() {
+        assert_eq!(options("query=q&rerank=3").unwrap().rerank_top_n, Some(3));
+        assert!(options("query=q&rerank=0").is_err());
+        assert!(options(&format!("query=q&rerank={}", MAX_TOP_K + 1)).is_err());
+    }
+}
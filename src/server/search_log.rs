@@ -0,0 +1,272 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{collections::{HashMap, VecDeque}, env, fs::{self, OpenOptions}, io::{ErrorKind, Write}, path::PathBuf, sync::{mpsc, Mutex}, thread};
+
+use crate::rag::SearchResult;
+
+/// Responses kept in memory for feedback lookups, feedback on older ones is rejected.
+const MAX_RECENT_RESPONSES: usize = 10_000;
+
+/// Lines waiting to be appended by the writer thread.
+static WRITER: Lazy<Mutex<mpsc::Sender<String>>> = Lazy::new(|| Mutex::new(spawn_writer()));
+
+/// The most recently logged responses, so feedback doesn't have to read the whole log.
+static RECENT_RESPONSES: Lazy<Mutex<RecentResponses>> = Lazy::new(Default::default);
+
+/// An entry of the append-only search log.
+///
+/// A search is logged when its sources are sent, its answer as a separate entry once the
+/// answer finished streaming.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LogEntry {
+    Search(SearchRecord),
+    Answer(AnswerRecord),
+    Feedback(FeedbackRecord),
+}
+
+/// What a search retrieved, recorded so unanswered questions can be found later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchRecord {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub query: String,
+    /// The query used for retrieval, differs from `query` for condensed follow-ups.
+    pub retrieval_query: String,
+    pub chunk_ids: Vec<String>,
+    pub doc_ids: Vec<String>,
+    pub top_scores: Vec<f32>,
+    pub best_score: Option<f32>,
+    pub fallback: bool,
+    pub query_embedding: Vec<f32>,
+}
+
+/// The answer generated for a search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerRecord {
+    /// Id of the search record the answer belongs to.
+    pub response_id: String,
+    pub answer: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+/// A student's feedback on an answer, stored with what the answer was based on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackRecord {
+    /// Id of the search record the feedback is about.
+    pub response_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub rating: Rating,
+    pub comment: Option<String>,
+    pub wrong_chunk_id: Option<String>,
+    pub query: String,
+    pub chunk_ids: Vec<String>,
+    pub answer: String,
+}
+
+impl SearchRecord {
+    pub fn new(query: &str, result: &SearchResult) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            query: query.to_string(),
+            retrieval_query: result.query.clone(),
+            chunk_ids: result.chunks.iter().map(|c| c.id.clone()).collect(),
+            doc_ids: result.chunks.iter().map(|c| c.doc_id.clone()).collect(),
+            top_scores: result.chunks.iter().map(|c| c.score).collect(),
+            best_score: result.best_score,
+            fallback: result.fallback,
+            query_embedding: result.query_embedding.clone(),
+        }
+    }
+}
+
+/// What feedback on a response is stored with.
+#[derive(Debug, Clone)]
+pub struct LoggedResponse {
+    pub query: String,
+    pub chunk_ids: Vec<String>,
+    /// Empty while the answer is still streaming.
+    pub answer: String,
+}
+
+#[derive(Debug, Default)]
+struct RecentResponses {
+    by_id: HashMap<String, LoggedResponse>,
+    /// Ids in logging order, the oldest is evicted first.
+    order: VecDeque<String>,
+}
+
+impl RecentResponses {
+    fn insert(&mut self, id: String, response: LoggedResponse) {
+        self.by_id.insert(id.clone(), response);
+        self.order.push_back(id);
+        while self.order.len() > MAX_RECENT_RESPONSES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.by_id.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl From<&SearchRecord> for LoggedResponse {
+    fn from(record: &SearchRecord) -> Self {
+        Self {
+            query: record.query.clone(),
+            chunk_ids: record.chunk_ids.clone(),
+            answer: String::new(),
+        }
+    }
+}
+
+/// Logs the search and makes it available to `find_response`.
+///
+/// # Errors
+/// - Returns an error if the log file can't be opened or written.
+pub fn log_search(record: SearchRecord) -> Result<()> {
+    let response = LoggedResponse::from(&record);
+    let id = record.id.clone();
+    append(&LogEntry::Search(record))?;
+    RECENT_RESPONSES.lock().unwrap().insert(id, response);
+    Ok(())
+}
+
+/// Logs the answer of the search `response_id` once it finished streaming.
+///
+/// # Errors
+/// - Returns an error if the log file can't be opened or written.
+pub fn log_answer(response_id: &str, answer: String) -> Result<()> {
+    if let Some(response) = RECENT_RESPONSES.lock().unwrap().by_id.get_mut(response_id) {
+        response.answer = answer.clone();
+    }
+    append(&LogEntry::Answer(AnswerRecord { response_id: response_id.to_string(), answer }))
+}
+
+/// Looks up a logged search with its answer among the most recent responses.
+///
+/// Only the in-memory index is searched, which `load_recent_responses` fills from the log
+/// on startup, so a lookup never reads the log file.
+pub fn find_response(response_id: &str) -> Option<LoggedResponse> {
+    RECENT_RESPONSES.lock().unwrap().by_id.get(response_id).cloned()
+}
+
+/// Fills the index of `find_response` with the most recent responses of the log.
+///
+/// # Errors
+/// - Returns an error if the log file exists but can't be read.
+pub fn load_recent_responses() -> Result<()> {
+    let mut loaded = RecentResponses::default();
+    for entry in read_entries()? {
+        match entry {
+            LogEntry::Search(record) => loaded.insert(record.id.clone(), LoggedResponse::from(&record)),
+            LogEntry::Answer(answer) => {
+                if let Some(response) = loaded.by_id.get_mut(&answer.response_id) {
+                    response.answer = answer.answer;
+                }
+            }
+            LogEntry::Feedback(_) => {}
+        }
+    }
+    println!("Loaded {} recent responses from the search log", loaded.order.len());
+    *RECENT_RESPONSES.lock().unwrap() = loaded;
+    Ok(())
+}
+
+/// The log is `search_log.jsonl` in `SEARCH_LOG_FOLDER`, kept apart from the uploaded files.
+fn log_path() -> PathBuf {
+    let folder = env::var("SEARCH_LOG_FOLDER").unwrap_or("./logs".to_string());
+    PathBuf::from(folder).join("search_log.jsonl")
+}
+
+/// Queues the entry to be appended to the log as a single JSON line.
+///
+/// The file is written by a dedicated thread, so request handlers never block on it. Write
+/// failures are reported there.
+///
+/// # Errors
+/// - Returns an error if the entry can't be serialized or the writer thread is gone.
+pub fn append(entry: &LogEntry) -> Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    WRITER
+        .lock()
+        .unwrap()
+        .send(line)
+        .map_err(|_| anyhow!("The search log writer stopped"))
+}
+
+/// Starts the thread appending queued lines to the log file in order.
+fn spawn_writer() -> mpsc::Sender<String> {
+    let (tx, rx) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        for line in rx {
+            if let Err(e) = write_line(&line) {
+                eprintln!("Failed writing the search log: {:?}", e);
+            }
+        }
+    });
+    tx
+}
+
+fn write_line(line: &str) -> Result<()> {
+    let path = log_path();
+    if let Some(folder) = path.parent() {
+        fs::create_dir_all(folder)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Reads every entry of the log, skipping lines that can't be parsed.
+///
+/// # Errors
+/// - Returns an error if the log file exists but can't be read.
+pub fn read_entries() -> Result<Vec<LogEntry>> {
+    let content = match fs::read_to_string(log_path()) {
+        Ok(c) => c,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(content
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| match serde_json::from_str(l) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("Skipping malformed search log entry: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(query: &str) -> LoggedResponse {
+        LoggedResponse { query: query.into(), chunk_ids: vec![], answer: String::new() }
+    }
+
+    #[test]
+    fn recent_responses_evict_the_oldest() {
+        let mut recent = RecentResponses::default();
+        for i in 0..=MAX_RECENT_RESPONSES {
+            recent.insert(format!("r{}", i), response(&format!("q{}", i)));
+        }
+
+        assert_eq!(recent.by_id.len(), MAX_RECENT_RESPONSES);
+        assert!(!recent.by_id.contains_key("r0"));
+        assert_eq!(recent.by_id[&format!("r{}", MAX_RECENT_RESPONSES)].query, format!("q{}", MAX_RECENT_RESPONSES));
+    }
+}